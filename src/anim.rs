@@ -0,0 +1,287 @@
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use image::RgbaImage;
+use serde::*;
+use crate::util::Individual;
+
+
+/// 动图格式
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimFormat {
+    Gif,
+    Apng,
+}
+impl AnimFormat {
+    /// 对应的文件扩展名
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimFormat::Gif => "gif",
+            AnimFormat::Apng => "png",
+        }
+    }
+}
+
+
+/// 演化过程延时动画的设置
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimelapseOptions {
+    pub format: AnimFormat,         // 动图格式 (gif, apng)
+    pub fps: f32,                   // 帧率
+    pub every: usize,               // 每隔多少张图片取一帧
+    pub max_frames: Option<usize>,  // 帧数上限, 超过时均匀抽取 (总是保留最后一帧)
+    pub max_dim: Option<u32>,       // 缩放到长边不超过 max_dim
+    pub output: Option<String>,     // 输出路径, 默认为文件夹内的 timelapse.gif 或 timelapse.png
+}
+impl Default for TimelapseOptions {
+    fn default() -> TimelapseOptions {
+        TimelapseOptions {
+            format: AnimFormat::Gif,
+            fps: 10.0,
+            every: 1,
+            max_frames: None,
+            max_dim: Some(400),
+            output: None,
+        }
+    }
+}
+impl TimelapseOptions {
+    /// 检查各项取值是否合法
+    pub fn validate(&self) {
+        assert!(self.fps > 0.0, "fps 必须为正数!");
+        assert!(self.every >= 1, "every 至少为 1!");
+        assert!(self.max_frames.is_none_or(|n| n >= 1), "max_frames 至少为 1!");
+        assert!(self.max_dim.is_none_or(|d| d >= 1), "max_dim 至少为 1!");
+    }
+}
+
+
+/// 逐帧写入的动图 (循环播放), 不需要把全部帧同时留在内存中. 各帧的尺寸必须相同
+pub struct AnimationWriter {
+    encoder: AnimationEncoder,
+    size: (u32, u32),           // 帧的宽, 高
+    delay_ms: u32,              // 每帧的时长
+}
+enum AnimationEncoder {
+    Gif(image::codecs::gif::GifEncoder<BufWriter<fs::File>>),
+    Apng(png::Writer<BufWriter<fs::File>>),
+}
+impl AnimationWriter {
+    /// 创建 path 处的动图. APNG 需要预先给出总帧数 n_frames
+    pub fn create(path: &str, (width, height): (u32, u32), n_frames: usize, fps: f32, format: AnimFormat) -> AnimationWriter {
+        assert!(n_frames >= 1, "没有可以写入的帧!");
+        let delay_ms = (1000.0 / fps).round().max(1.0) as u32;
+        let file = BufWriter::new(fs::File::create(path).unwrap());
+        let encoder = match format {
+            AnimFormat::Gif => {
+                let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(file, 10);
+                encoder.set_repeat(image::codecs::gif::Repeat::Infinite).unwrap();
+                AnimationEncoder::Gif(encoder)
+            }
+            AnimFormat::Apng => {
+                let mut encoder = png::Encoder::new(file, width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(n_frames as u32, 0).unwrap();
+                encoder.set_frame_delay(delay_ms as u16, 1000).unwrap();
+                AnimationEncoder::Apng(encoder.write_header().unwrap())
+            }
+        };
+        AnimationWriter { encoder, size: (width, height), delay_ms }
+    }
+
+    /// 写入下一帧
+    pub fn write_frame(&mut self, frame: &RgbaImage) {
+        assert_eq!(frame.dimensions(), self.size, "各帧的尺寸必须相同!");
+        match &mut self.encoder {
+            AnimationEncoder::Gif(encoder) => {
+                let delay = image::Delay::from_numer_denom_ms(self.delay_ms, 1);
+                encoder.encode_frame(image::Frame::from_parts(frame.clone(), 0, 0, delay)).unwrap();
+            }
+            AnimationEncoder::Apng(writer) => writer.write_image_data(frame.as_raw()).unwrap(),
+        }
+    }
+
+    /// 写完全部帧后结束文件
+    pub fn finish(self) {
+        match self.encoder {
+            AnimationEncoder::Gif(encoder) => drop(encoder),
+            AnimationEncoder::Apng(writer) => writer.finish().unwrap(),
+        }
+    }
+}
+
+
+/// 缩放到长边不超过 max_dim
+pub fn fit_within(frame: RgbaImage, max_dim: Option<u32>) -> RgbaImage {
+    let (width, height) = frame.dimensions();
+    match max_dim {
+        Some(max_dim) if width.max(height) > max_dim => {
+            let ratio = max_dim as f32 / width.max(height) as f32;
+            let new_width = ((width as f32 * ratio).round() as u32).max(1);
+            let new_height = ((height as f32 * ratio).round() as u32).max(1);
+            image::imageops::resize(&frame, new_width, new_height, image::imageops::FilterType::Triangle)
+        }
+        _ => frame,
+    }
+}
+
+
+/// 文件夹中文件名里带有编号的全部 PNG 图片, 按编号排序 (例如 ./src/result/generation_best_*/ 中的 {gen}.png)
+pub fn numbered_frames(folder: &str) -> Vec<PathBuf> {
+    let mut frames: Vec<(u64, PathBuf)> = fs::read_dir(folder).unwrap()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")))
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_str()?;
+            // 取文件名中的第一串数字作为编号
+            let digits: String = stem.chars().skip_while(|c| !c.is_ascii_digit()).take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse().ok().map(|number| (number, path))
+        })
+        .collect();
+    frames.sort();
+    frames.into_iter().map(|(_, path)| path).collect()
+}
+
+
+/// 按照 opts 选出帧: 先每隔 every 张取一帧, 再均匀抽取至多 max_frames 帧. 总是保留最后一帧
+fn select_frames<T: Clone>(items: &[T], opts: &TimelapseOptions) -> Vec<T> {
+    let mut selected: Vec<T> = items.iter().step_by(opts.every).cloned().collect();
+    if let Some(last) = items.last() {
+        if !(items.len() - 1).is_multiple_of(opts.every) {
+            selected.push(last.clone());
+        }
+    }
+    if let Some(max_frames) = opts.max_frames {
+        if selected.len() > max_frames {
+            let n = selected.len();
+            selected = (0..max_frames)
+                .map(|k| if max_frames == 1 { n - 1 } else { k * (n - 1) / (max_frames - 1) })
+                .map(|i| selected[i].clone())
+                .collect();
+        }
+    }
+    selected
+}
+
+
+/// 将一次演化保存下来的各代图片合成为延时动画, 返回输出路径
+pub fn timelapse(folder: &str, opts: &TimelapseOptions) -> String {
+    let paths = select_frames(&numbered_frames(folder), opts);
+    assert!(!paths.is_empty(), "文件夹 {} 中没有带编号的 PNG 图片!", folder);
+    let output = opts.output.clone().unwrap_or_else(|| {
+        Path::new(folder).join(format!("timelapse.{}", opts.format.extension())).to_string_lossy().into_owned()
+    });
+    // 逐张读取并写入, 不把全部帧同时留在内存中
+    let read_frame = |path: &PathBuf| fit_within(image::open(path).unwrap().to_rgba8(), opts.max_dim);
+    let first = read_frame(&paths[0]);
+    let mut writer = AnimationWriter::create(&output, first.dimensions(), paths.len(), opts.fps, opts.format);
+    writer.write_frame(&first);
+    for path in &paths[1..] {
+        writer.write_frame(&read_frame(path));
+    }
+    writer.finish();
+    output
+}
+
+
+/// 逐个图形叠加动画的输出格式
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildUpFormat {
+    Gif,
+    Apng,
+    Svg,    // SMIL 动画
+}
+
+
+/// 逐个图形叠加动画的设置
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildUpOptions {
+    pub format: BuildUpFormat,      // 输出格式 (gif, apng, svg)
+    pub fps: f32,                   // 帧率
+    pub shapes_per_frame: usize,    // 每帧新增的图形数目
+    pub fade_frames: usize,         // 新图形淡入所用的帧数, 为 0 时直接出现
+    pub hold_frames: usize,         // 完成后最后一帧额外停留的帧数 (仅 gif, apng)
+    pub max_dim: Option<u32>,       // 缩放到长边不超过 max_dim (仅 gif, apng)
+    pub output: Option<String>,     // 输出路径, 默认为个体文件同目录下的 *_buildup.gif / .png / .svg
+}
+impl Default for BuildUpOptions {
+    fn default() -> BuildUpOptions {
+        BuildUpOptions {
+            format: BuildUpFormat::Gif,
+            fps: 20.0,
+            shapes_per_frame: 1,
+            fade_frames: 0,
+            hold_frames: 40,
+            max_dim: Some(400),
+            output: None,
+        }
+    }
+}
+impl BuildUpOptions {
+    /// 检查各项取值是否合法
+    pub fn validate(&self) {
+        assert!(self.fps > 0.0, "fps 必须为正数!");
+        assert!(self.shapes_per_frame >= 1, "shapes_per_frame 至少为 1!");
+        assert!(self.max_dim.is_none_or(|d| d >= 1), "max_dim 至少为 1!");
+    }
+}
+
+
+/// 将个体按 z 序逐个画出图形的过程导出为动画, 返回输出路径
+pub fn build_up(ind: &Individual, genome_path: &str, opts: &BuildUpOptions) -> String {
+    let extension = match opts.format {
+        BuildUpFormat::Gif => "gif",
+        BuildUpFormat::Apng => "png",
+        BuildUpFormat::Svg => "svg",
+    };
+    let output = opts.output.clone().unwrap_or_else(|| {
+        format!("{}_buildup.{}", Path::new(genome_path).with_extension("").display(), extension)
+    });
+
+    let format = match opts.format {
+        BuildUpFormat::Gif => AnimFormat::Gif,
+        BuildUpFormat::Apng => AnimFormat::Apng,
+        BuildUpFormat::Svg => {
+            // 与 gif / apng 相同的节奏: 每组图形占 (fade_frames + 1) 帧
+            let interval = (opts.fade_frames + 1) as f32 / opts.fps / opts.shapes_per_frame as f32;
+            let svg = ind.to_animated_svg(interval, opts.fade_frames as f32 / opts.fps);
+            fs::write(&output, svg).unwrap();
+            return output;
+        }
+    };
+
+    // 先缩小个体再绘制, 比绘制后再缩小图片快得多
+    let (x_height, y_width) = ind.env_size();
+    let ind = match opts.max_dim {
+        Some(max_dim) if x_height.max(y_width) > max_dim as usize => {
+            let ratio = max_dim as f32 / x_height.max(y_width) as f32;
+            ind.rescaled(((x_height as f32 * ratio).round() as usize).max(1), ((y_width as f32 * ratio).round() as usize).max(1))
+        }
+        _ => ind.clone(),
+    };
+    // 边绘制边写入, 内存中只有当前一帧
+    let (x_height, y_width) = ind.env_size();
+    let n_frames = ind.build_up_frame_count(opts.shapes_per_frame, opts.fade_frames) + opts.hold_frames;
+    let mut writer = AnimationWriter::create(&output, (y_width as u32, x_height as u32), n_frames, opts.fps, format);
+    let mut last = None;
+    ind.for_each_build_up_frame(opts.shapes_per_frame, opts.fade_frames, |canvas| {
+        let frame = canvas.to_image();
+        writer.write_frame(&frame);
+        last = Some(frame);
+    });
+    let last = last.unwrap();
+    for _ in 0..opts.hold_frames {
+        writer.write_frame(&last);
+    }
+    writer.finish();
+    output
+}
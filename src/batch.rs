@@ -0,0 +1,90 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+
+/// 批量模式会处理的图片扩展名
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "gif", "webp", "tif", "tiff"];
+
+
+/// 列出批量处理的全部目标图片 (按路径排序).
+/// spec 为文件夹时取其中全部图片; 否则视为通配模式, 文件名部分可以使用 * 和 ?, 如 ./photos/avatar_*.jpg
+pub fn list_targets(spec: &str) -> Vec<PathBuf> {
+    let path = Path::new(spec);
+    let (folder, pattern) = if path.is_dir() {
+        (path, "*")
+    } else {
+        let pattern = path.file_name().and_then(|name| name.to_str()).unwrap_or_else(|| panic!("无法识别的路径 {}", spec));
+        (path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new(".")), pattern)
+    };
+    let mut targets: Vec<PathBuf> = fs::read_dir(folder).unwrap_or_else(|e| panic!("无法读取文件夹 {}: {}", folder.display(), e))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension().and_then(|ext| ext.to_str())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| wildcard_match(pattern, name)))
+        .collect();
+    targets.sort();
+    targets
+}
+
+
+/// name 是否匹配通配模式 pattern (* 匹配任意多个字符, ? 匹配一个字符)
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    // matched[j]: pattern 的当前前缀能否匹配 name[..j]
+    let mut matched = vec![false; name.len() + 1];
+    matched[0] = true;
+    for &p in &pattern {
+        let mut next = vec![false; name.len() + 1];
+        for j in 0..=name.len() {
+            next[j] = match p {
+                '*' => matched[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matched[j - 1],
+                c => j > 0 && matched[j - 1] && name[j - 1] == c,
+            };
+        }
+        matched = next;
+    }
+    matched[name.len()]
+}
+
+
+/// 一张图片的处理结果
+pub struct BatchResult {
+    pub target: String,
+    pub outcome: Result<BatchStats, String>,    // 演化的统计, 或失败的原因 (如图片无法读取)
+}
+
+
+/// 一张图片成功演化后的统计
+pub struct BatchStats {
+    pub generations: usize,
+    pub seconds: f32,
+    pub fitness: f32,
+    pub n_shapes: usize,
+    pub stop_reason: String,
+}
+
+
+/// 将全部结果写为 CSV 汇总报告: 失败的图片只有 error 一列. 最后一行是成功的图片的平均值
+pub fn write_report(path: &str, results: &[BatchResult]) {
+    let mut file = fs::File::create(path).unwrap();
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    writeln!(file, "target,generations,seconds,fitness,n_shapes,stop_reason,error").unwrap();
+    for r in results {
+        match &r.outcome {
+            Ok(s) => writeln!(file, "{},{},{:.1},{},{},{},", quote(&r.target), s.generations, s.seconds, s.fitness, s.n_shapes, quote(&s.stop_reason)),
+            Err(error) => writeln!(file, "{},,,,,,{}", quote(&r.target), quote(error)),
+        }.unwrap();
+    }
+    let stats: Vec<&BatchStats> = results.iter().filter_map(|r| r.outcome.as_ref().ok()).collect();
+    if !stats.is_empty() {
+        let n = stats.len() as f32;
+        let mean = |f: fn(&BatchStats) -> f32| stats.iter().map(|s| f(s)).sum::<f32>() / n;
+        writeln!(file, "\"(平均)\",{:.1},{:.1},{},{:.1},,", mean(|s| s.generations as f32), mean(|s| s.seconds),
+                 mean(|s| s.fitness), mean(|s| s.n_shapes as f32)).unwrap();
+    }
+}
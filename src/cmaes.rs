@@ -0,0 +1,147 @@
+use crate::util::*;
+
+
+/// 对角协方差的 CMA-ES (sep-CMA-ES, Ros & Hansen 2008).
+/// 只维护协方差矩阵的对角线, 因此每代的开销是 O(n), 适用于上千维的参数向量.
+pub struct SepCmaEs {
+    n: usize,
+    lambda: usize,          // 每代采样数
+    weights: Vec<f32>,      // 前 mu 名的重组权重
+    mu_eff: f32,
+    c_sigma: f32,
+    d_sigma: f32,
+    c_c: f32,
+    c_1: f32,
+    c_mu: f32,
+    chi_n: f32,             // E‖N(0, I)‖
+    mean: Vec<f32>,
+    sigma: f32,
+    diag_c: Vec<f32>,       // 协方差矩阵的对角线
+    p_sigma: Vec<f32>,      // 步长的演化路径
+    p_c: Vec<f32>,          // 协方差的演化路径
+    n_gen: usize,
+}
+impl SepCmaEs {
+    /// 以 mean 为起点, sigma 为初始步长
+    pub fn new(mean: Vec<f32>, sigma: f32) -> SepCmaEs {
+        let n = mean.len();
+        let nf = n as f32;
+        let lambda = 4 + (3.0 * nf.ln()).floor() as usize;
+        let mu = lambda / 2;
+        let raw_weights: Vec<f32> = (0..mu).map(|i| (mu as f32 + 0.5).ln() - ((i + 1) as f32).ln()).collect();
+        let sum: f32 = raw_weights.iter().sum();
+        let weights: Vec<f32> = raw_weights.iter().map(|w| w / sum).collect();
+        let mu_eff = 1.0 / weights.iter().map(|w| w * w).sum::<f32>();
+
+        let c_sigma = (mu_eff + 2.0) / (nf + mu_eff + 5.0);
+        let d_sigma = 1.0 + 2.0 * f32::max(0.0, ((mu_eff - 1.0) / (nf + 1.0)).sqrt() - 1.0) + c_sigma;
+        let c_c = (4.0 + mu_eff / nf) / (nf + 4.0 + 2.0 * mu_eff / nf);
+        // 对角协方差的学习率可以比完整协方差大 (n+2)/3 倍
+        let sep_factor = (nf + 2.0) / 3.0;
+        let c_1 = f32::min(1.0, sep_factor * 2.0 / ((nf + 1.3).powi(2) + mu_eff));
+        let c_mu = f32::min(1.0 - c_1, sep_factor * 2.0 * (mu_eff - 2.0 + 1.0 / mu_eff) / ((nf + 2.0).powi(2) + mu_eff));
+        let chi_n = nf.sqrt() * (1.0 - 1.0 / (4.0 * nf) + 1.0 / (21.0 * nf * nf));
+
+        SepCmaEs {
+            n, lambda, weights, mu_eff, c_sigma, d_sigma, c_c, c_1, c_mu, chi_n,
+            mean,
+            sigma,
+            diag_c: vec![1.0; n],
+            p_sigma: vec![0.0; n],
+            p_c: vec![0.0; n],
+            n_gen: 0,
+        }
+    }
+
+    /// 当前的步长
+    pub fn sigma(&self) -> f32 {
+        self.sigma
+    }
+
+    /// 采样 lambda 个候选解
+    pub fn ask(&self) -> Vec<Vec<f32>> {
+        (0..self.lambda).map(|_| {
+            (0..self.n).map(|k| {
+                self.mean[k] + self.sigma * self.diag_c[k].sqrt() * random::normal(1.0)
+            }).collect()
+        }).collect()
+    }
+
+    /// 告知 ask() 所得各候选解的适应度 (越小越好), 更新分布
+    #[allow(clippy::needless_range_loop)]
+    pub fn tell(&mut self, candidates: &[Vec<f32>], fitness: &[f32]) {
+        debug_assert!(candidates.len() == self.lambda && fitness.len() == self.lambda);
+        let mut order: Vec<usize> = (0..self.lambda).collect();
+        order.sort_by(|&i, &j| fitness[i].partial_cmp(&fitness[j]).unwrap());
+        self.n_gen += 1;
+
+        // 重组得到新均值, y_w = (新均值 - 旧均值) / sigma
+        let old_mean = self.mean.clone();
+        for k in 0..self.n {
+            self.mean[k] = self.weights.iter().zip(&order).map(|(w, &i)| w * candidates[i][k]).sum();
+        }
+        let y_w: Vec<f32> = (0..self.n).map(|k| (self.mean[k] - old_mean[k]) / self.sigma).collect();
+
+        // 更新步长的演化路径
+        let cs_coef = (self.c_sigma * (2.0 - self.c_sigma) * self.mu_eff).sqrt();
+        for k in 0..self.n {
+            self.p_sigma[k] = (1.0 - self.c_sigma) * self.p_sigma[k] + cs_coef * y_w[k] / self.diag_c[k].sqrt();
+        }
+        let p_sigma_norm = self.p_sigma.iter().map(|p| p * p).sum::<f32>().sqrt();
+
+        // 步长路径过长时暂停协方差路径的累积, 防止协方差在步长增大阶段过快膨胀
+        let threshold = (1.4 + 2.0 / (self.n as f32 + 1.0)) * self.chi_n
+            * (1.0 - (1.0 - self.c_sigma).powi(2 * self.n_gen as i32)).sqrt();
+        let h_sigma = if p_sigma_norm < threshold { 1.0 } else { 0.0 };
+
+        // 更新协方差的演化路径和对角协方差
+        let cc_coef = (self.c_c * (2.0 - self.c_c) * self.mu_eff).sqrt();
+        for k in 0..self.n {
+            self.p_c[k] = (1.0 - self.c_c) * self.p_c[k] + h_sigma * cc_coef * y_w[k];
+            let rank_mu: f32 = self.weights.iter().zip(&order)
+                .map(|(w, &i)| w * ((candidates[i][k] - old_mean[k]) / self.sigma).powi(2))
+                .sum();
+            let rank_one = self.p_c[k].powi(2) + (1.0 - h_sigma) * self.c_c * (2.0 - self.c_c) * self.diag_c[k];
+            self.diag_c[k] = (1.0 - self.c_1 - self.c_mu) * self.diag_c[k] + self.c_1 * rank_one + self.c_mu * rank_mu;
+        }
+
+        // 更新步长
+        self.sigma *= ((self.c_sigma / self.d_sigma) * (p_sigma_norm / self.chi_n - 1.0)).exp();
+    }
+}
+
+
+/// 固定图形的数目和顺序, 用 sep-CMA-ES 对个体的全部连续参数 (坐标、半径、颜色、透明度) 做局部精修.
+/// 在归一化空间中搜索: 实际参数 = 初始参数 + 量纲 ⊙ x, 初始时 x = 0.
+///  - `colors`: 颜色的取值约束, 每个候选解的颜色都投影到其中
+///  - `sigma`: 归一化空间中的初始步长, 即坐标按画布短边、颜色按 255、透明度按 1 的比例
+///  - `n_iters`: 迭代代数
+///  - `on_iter`: 每代结束后的回调, 参数为 (代数, 至今最优个体, 当前步长)
+pub fn polish<F: FnMut(usize, &Individual, f32)>(ind: &Individual, target: &Canvas, colors: &ColorModel, sigma: f32, n_iters: usize, mut on_iter: F) -> Individual {
+    let (x0, scales) = ind.params();
+    let to_params = |x: &[f32]| -> Vec<f32> {
+        x0.iter().zip(&scales).zip(x).map(|((p, s), x)| p + s * x).collect()
+    };
+
+    let mut best = ind.clone();
+    best.calc_fitness(target);
+    if x0.is_empty() { return best }
+
+    let mut es = SepCmaEs::new(vec![0.0; x0.len()], sigma);
+    let mut candidate = ind.clone();
+    for iter in 1..=n_iters {
+        let xs = es.ask();
+        let mut fitness = Vec::with_capacity(xs.len());
+        for x in &xs {
+            candidate.set_params(&to_params(x), colors);
+            candidate.calc_fitness(target);
+            fitness.push(candidate.get_fitness());
+            if candidate.get_fitness() < best.get_fitness() {
+                best = candidate.clone();
+            }
+        }
+        es.tell(&xs, &fitness);
+        on_iter(iter, &best, es.sigma());
+    }
+    best
+}
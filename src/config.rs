@@ -0,0 +1,488 @@
+use serde::*;
+use crate::util::{random, AlphaMode, Canvas, Channels, ColorModel, Filter, Individual};
+use crate::palette;
+use crate::anim::TimelapseOptions;
+use crate::metrics::MetricsFormat;
+
+
+/// 岛屿之间的迁移拓扑
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    Ring,               // 第 i 个岛屿只向第 i+1 个岛屿迁移
+    FullyConnected,     // 每个岛屿都向其余全部岛屿迁移
+}
+
+
+/// 颜色模式
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    Rgb,        // 彩色
+    Gray,       // 灰度: 目标转为亮度, 图形只有一个灰度值
+    Ink,        // 单色: 目标转为亮度, 图形颜色固定为 ink_color, 只演化透明度 (适用于绘图仪、单色印刷)
+}
+
+
+/// 初始背景色的选取方式
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BgMode {
+    Fixed,      // 使用 bg_color
+    Mean,       // 目标图片的平均色
+    Median,     // 目标图片各通道的中位数
+    Dominant,   // 目标图片中出现最多的颜色
+}
+
+
+/// 从每一代的候选个体中选出存活者的策略 (最优个体总是存活)
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    Truncation,                 // 截断选择: 直接保留得分最好的若干个
+    Tournament { size: usize }, // 锦标赛选择: 每次随机抽取 size 个, 保留其中得分最好的
+    Roulette,                   // 轮盘赌选择: 按 (最差得分 - 得分) 的比例抽取
+    Rank,                       // 排名选择: 按排名线性递减的概率抽取
+}
+
+
+/// 保存最优个体的时机 (第 1 轮总是保存)
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaveSchedule {
+    Cascade,                        // 越到后期越难进化, 保存频率逐渐降低: 前 100 轮每轮, 之后到 1000 轮每 10 轮, 依此类推
+    Every { n: usize },             // 每 n 轮
+    Improvement { ratio: f32 },     // 最优适应度比上次保存时降低了 ratio (如 0.01 即 1%) 以上
+    Interval { seconds: f32 },      // 距上次保存经过了 seconds 秒以上
+    LogSpaced { per_decade: usize },    // 轮数按对数均匀分布, 每 10 倍保存 per_decade 次
+}
+impl SaveSchedule {
+    /// 第 gen 轮 (最优适应度为 fitness) 是否需要保存. last 为上次保存时的 (适应度, 距今时长), 尚未保存过则为 None
+    pub fn is_due(&self, gen: usize, fitness: f32, last: Option<(f32, std::time::Duration)>) -> bool {
+        let Some((last_fitness, elapsed)) = last else { return true };
+        match *self {
+            SaveSchedule::Cascade => {
+                (gen <= 100) ||
+                (gen <= 1000 && gen.is_multiple_of(10)) ||
+                (gen <= 10000 && gen.is_multiple_of(100)) ||
+                (gen <= 100000 && gen.is_multiple_of(1000)) ||
+                (gen.is_multiple_of(10000))
+            }
+            SaveSchedule::Every { n } => gen.is_multiple_of(n),
+            SaveSchedule::Improvement { ratio } => fitness < last_fitness * (1.0 - ratio),
+            SaveSchedule::Interval { seconds } => elapsed.as_secs_f32() >= seconds,
+            SaveSchedule::LogSpaced { per_decade } => {
+                // 第 k 个保存点为 round(10^(k / per_decade)), 只需检查 gen 附近的几个 k
+                let k = ((gen as f64).log10() * per_decade as f64).round() as i64;
+                (k - 1..=k + 1).any(|k| 10f64.powf(k as f64 / per_decade as f64).round() as usize == gen)
+            }
+        }
+    }
+}
+
+
+/// 演化的全部超参数. 可以由 JSON 文件 (--config path) 给出, 也可以逐项在命令行覆盖 (--pop_size 8)
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub target: String,             // 目标图片路径
+    pub crop: Option<[u32; 4]>,     // 读取时先裁剪出 [left, top, width, height] 区域 (单位为像素)
+    pub max_dim: Option<usize>,     // 读取时缩放到长边不超过 max_dim, 演化在缩放后的尺寸上进行, 输出仍按原尺寸渲染
+    pub resize_filter: Filter,      // 缩放目标图片时使用的重采样滤波器 (nearest, triangle, catmull_rom, gaussian, lanczos3)
+
+    pub pop_size: usize,            // 种群大小. 取值范围 [1, ∞)
+    pub prop_amount: usize,         // 每个个体的产仔数. 取值范围 [1, ∞)
+    pub n_guard: usize,             // 上一轮的前 n_guard 个个体也参与本轮竞争 (而非产仔后立刻抛弃). 取值范围 [0, pop_size]
+
+    pub bg_color: (f32, f32, f32),  // 背景色
+    pub bg_mode: BgMode,            // 初始背景色的选取方式 (fixed: 即 bg_color, mean, median, dominant: 由目标图片计算)
+    pub pr_mutate_bg: f32,          // 每个新个体的背景色发生变异的概率. 为 0 时背景色固定不变
+    pub transparent_bg: bool,       // 是否使用透明背景 (代替 bg_color), 输出为 RGBA 图片
+    pub alpha_mode: AlphaMode,      // 目标图片的透明区域在适应度中的处理方式 (ignore: 不参与比较, match: 透明度也要匹配)
+    pub save_svg: bool,             // 保存图片时是否同时保存 SVG
+    pub save_genome: bool,          // 保存图片时是否同时保存个体的 JSON (best.json 总是保存最新的一个)
+    pub save_binary: bool,          // 保存个体的 JSON 时是否同时保存紧凑的二进制格式 (*.eag), 检查点是否保存为二进制的 checkpoint.eag (代替 checkpoint.json)
+    pub save_schedule: SaveSchedule,    // 保存的时机, 如 "cascade", {"every": {"n": 50}}, {"improvement": {"ratio": 0.01}}, {"interval": {"seconds": 60}}, {"log_spaced": {"per_decade": 10}}
+    pub output_dir: String,         // 输出目录, 每次运行在其中新建一个子文件夹
+    pub filename: String,           // 保存文件名 (不含扩展名) 的模板, 可用 {gen}, {fitness}, {n_shapes}. 延时动画按文件名中的第一个数字排序
+    pub max_generations: Option<usize>,     // 停止条件: 演化到这一轮为止
+    pub max_seconds: Option<f32>,           // 停止条件: 演化超过这么多秒
+    pub target_fitness: Option<f32>,        // 停止条件: 最优适应度不高于此值
+    pub max_shapes: Option<usize>,          // 停止条件: 最优个体的图形数目达到此值
+    pub patience: Option<usize>,            // 停止条件: 连续这么多轮最优适应度没有改进
+    pub resume: Option<String>,             // 从 checkpoint.json 或 checkpoint.eag (运行结束或 Ctrl-C 时保存) 恢复运行
+    pub jobs: usize,                        // batch 模式同时处理的图片数目
+    pub seed: Option<u64>,                  // 随机数种子, 不指定时随机生成. 记录在基因文件的元数据中, 以相同的配置和种子可以复现运行
+
+    pub video_first_generations: usize,     // video 模式第一帧演化的轮数
+    pub video_generations: usize,           // video 模式之后每一帧 (从上一帧的种群出发) 演化的轮数
+    pub temporal_weight: f32,               // video 模式的时间连贯性惩罚: 适应度 + temporal_weight × 与上一帧最优个体的基因差异
+
+    pub serve: Option<u16>,         // 若给出, 在 127.0.0.1 的这个端口上启动 HTTP 服务: 实时预览、进度推送 (SSE), 并可在运行中修改 TUNABLE_FIELDS 中的参数
+    pub tui: bool,                  // 是否显示终端仪表盘 (预览、适应度曲线, 可按键暂停、保存、修剪), 代替逐轮打印
+    pub metrics: Option<MetricsFormat>, // 每轮的指标 (适应度、图形数目、接受率、速度等) 写入保存文件夹中的 metrics.csv (csv) 或 metrics.jsonl (jsonl), 为 null 时不写
+
+    pub mutate_ratio: f32,          // 最多多少比例的图形发生变异
+    pub mutate_amp: f32,            // 变异剧烈程度
+    pub adaptive_amp: bool,         // 是否按 1/5 成功法则自适应调整变异剧烈程度 (否则固定为 mutate_amp)
+    pub adapt_window: Option<usize>,    // 每累计多少次变异调整一次剧烈程度, 为 null 时取 10 轮的变异次数 (10 × pop_size × prop_amount)
+    pub color_mode: ColorMode,          // 颜色模式 (rgb, gray, ink). 非 rgb 时忽略调色板
+    pub ink_color: String,              // ink 模式下图形使用的墨水色 (十六进制)
+
+    pub palette: Vec<String>,           // 图形只能使用的颜色 (十六进制), 如 ["#1d3557", "#e63946"]. 为空时不限定
+    pub palette_image: Option<String>,  // 从这张图片中提取调色板 (其中的颜色多于 palette_size 种时用 k-means 聚类)
+    pub palette_from_target: bool,      // 是否用 k-means 从目标图片中提取 palette_size 种颜色作为调色板
+    pub palette_size: usize,            // 提取调色板时的颜色数目
+    // 以上三种调色板来源的优先级为 palette > palette_image > palette_from_target
+
+    pub optimal_color: bool,        // 图形发生几何变异或新增后, 是否直接将其 RGB 设为覆盖区域上的最小二乘最优色
+
+    pub pr_add_shape: f32,          // 每个新个体尝试新增一个图形的概率
+    pub pr_remove_shape: Option<f32>,   // 每个新个体随机移除一个图形的概率, 为 null 时取 0 (pareto 模式下取 0.1, 否则图形数目只增不减)
+    pub pr_triangle: f32,           // 使用三角形的概率权重
+    pub pr_circle: f32,             // 使用圆形的概率权重
+    pub pr_rectangle: f32,          // 使用长方形的概率权重
+    // 在生成时, 首先按照 pr_add_shape 决定 ｢是否生成｣. 如果 ｢是｣, 再根据三个图形的概率权重抽取其中一个进行生成.
+    // 这里后三个变量在交互上可以实现为 ｢等边三角图｣
+
+    pub selection: Selection,       // 选择策略
+    pub novelty_weight: f32,        // 多样性奖励的权重: 得分 = fitness - novelty_weight × 与最近 novelty_k 个个体的平均基因差异. 为 0 时不奖励
+    pub novelty_k: usize,           // 计算多样性时参考的最近邻个数
+
+    pub pyramid: Vec<(usize, f32)>, // 由粗到细的演化日程: 从第 gen 轮起在 scale 倍分辨率下演化, 如 [[1, 0.25], [2000, 0.5], [5000, 1.0]]. 为空时始终用原分辨率
+
+    pub n_islands: usize,           // 岛屿 (独立演化的子种群) 数目, 为 1 时即普通的单种群
+    pub migration_interval: usize,  // 每隔多少轮进行一次迁移
+    pub n_migrants: usize,          // 每次迁移时每个岛屿送出的最优个体数目
+    pub topology: Topology,         // 迁移拓扑
+    pub parallel: bool,             // 各岛屿是否在各自的线程上演化
+
+    pub pareto_pop_size: usize,     // pareto 模式 (NSGA-II) 的种群大小
+    pub pareto_generations: usize,  // pareto 模式的演化轮数
+    pub pareto_export_interval: usize,  // pareto 模式每隔多少轮导出一次当前的 Pareto 前沿
+
+    pub timelapse: Option<TimelapseOptions>,    // 若给出, 运行结束时用保存文件夹中的全部图片生成延时动画, 如 {"format": "gif", "fps": 10}
+}
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            target: "./src/data/target.jpg".to_string(),
+            crop: None,
+            max_dim: None,
+            resize_filter: Filter::Triangle,
+            pop_size: 4,
+            prop_amount: 4,
+            n_guard: 2,
+            bg_color: (0., 0., 0.),
+            bg_mode: BgMode::Fixed,
+            pr_mutate_bg: 0.0,
+            transparent_bg: false,
+            alpha_mode: AlphaMode::Ignore,
+            save_svg: false,
+            save_genome: false,
+            save_binary: false,
+            save_schedule: SaveSchedule::Cascade,
+            output_dir: "./src/result".to_string(),
+            filename: "{gen}".to_string(),
+            max_generations: None,
+            max_seconds: None,
+            target_fitness: None,
+            max_shapes: None,
+            patience: None,
+            resume: None,
+            jobs: 1,
+            seed: None,
+            video_first_generations: 3000,
+            video_generations: 300,
+            temporal_weight: 20.0,
+            serve: None,
+            tui: false,
+            metrics: Some(MetricsFormat::Csv),
+            mutate_ratio: 0.1,
+            mutate_amp: 1.0,
+            adaptive_amp: false,
+            adapt_window: None,
+            color_mode: ColorMode::Rgb,
+            ink_color: "#000000".to_string(),
+            palette: Vec::new(),
+            palette_image: None,
+            palette_from_target: false,
+            palette_size: 16,
+            optimal_color: false,
+            pr_add_shape: 0.5,
+            pr_remove_shape: None,
+            #[allow(clippy::approx_constant)]
+            pr_triangle: 3.14,
+            pr_circle: 1.23,
+            pr_rectangle: 1.35,
+            selection: Selection::Truncation,
+            novelty_weight: 0.0,
+            novelty_k: 3,
+            pyramid: Vec::new(),
+            n_islands: 1,
+            migration_interval: 50,
+            n_migrants: 1,
+            topology: Topology::Ring,
+            parallel: true,
+            pareto_pop_size: 32,
+            pareto_generations: 10000,
+            pareto_export_interval: 500,
+            timelapse: None,
+        }
+    }
+}
+impl Config {
+    /// 允许在运行中修改的参数 (其余参数在启动时就已生效, 如目标图片、调色板、岛屿数目等)
+    pub const TUNABLE_FIELDS: &'static [&'static str] = &[
+        "pop_size", "prop_amount", "n_guard", "pr_mutate_bg", "save_svg", "save_genome", "save_binary", "save_schedule",
+        "mutate_ratio", "mutate_amp", "optimal_color", "pr_add_shape", "pr_remove_shape", "pr_triangle", "pr_circle", "pr_rectangle",
+        "selection", "novelty_weight", "novelty_k", "migration_interval", "n_migrants", "topology",
+    ];
+
+    /// 从命令行参数构造. 先读取 --config 指定的 JSON 文件 (若有), 再用其余的 --key value 逐项覆盖
+    pub fn from_args(args: &[String]) -> Config {
+        let config: Config = parse_args(args);
+        config.validate();
+        config
+    }
+
+    /// 按照 crop, max_dim, resize_filter 读取并预处理目标图片. 同时返回裁剪后、缩放前的原始尺寸
+    pub fn load_target(&self) -> (Canvas, (usize, usize)) {
+        let (target, original_size) = Canvas::read_from_file_with(&self.target, self.crop, self.max_dim, self.resize_filter);
+        (self.prepare_target(target), original_size)
+    }
+
+    /// 以 seed 初始化当前线程的随机数生成器. 没有指定 seed 时随机生成一个并记录下来
+    pub fn seed_rng(&mut self) {
+        random::seed(*self.seed.get_or_insert_with(random::fresh_seed));
+    }
+
+    /// 按照 alpha_mode 和 color_mode 设置目标图片的比对方式
+    pub fn prepare_target(&self, mut target: Canvas) -> Canvas {
+        target.alpha_mode = self.alpha_mode;
+        if self.color_mode != ColorMode::Rgb {
+            target.convert_to_luminance();
+        }
+        if self.color_mode == ColorMode::Gray {
+            target.channels = Channels::Gray;    // 个体也都是灰色, 只需比较一个通道
+        }
+        target
+    }
+
+    /// 按照调色板相关的设置, 构造图形颜色的取值约束
+    pub fn color_model(&self, target: &Canvas) -> ColorModel {
+        if self.color_mode == ColorMode::Gray {
+            ColorModel::Gray
+        } else if self.color_mode == ColorMode::Ink {
+            let (r, g, b) = palette::parse_hex(&self.ink_color).unwrap_or_else(|| panic!("无法解析的颜色 {}", self.ink_color));
+            ColorModel::Ink(r, g, b)
+        } else if !self.palette.is_empty() {
+            let colors = self.palette.iter()
+                .map(|hex| palette::parse_hex(hex).unwrap_or_else(|| panic!("无法解析的颜色 {}", hex)))
+                .collect();
+            ColorModel::Palette(colors)
+        } else if let Some(path) = &self.palette_image {
+            ColorModel::Palette(palette::extract(&Canvas::read_from_file(path), self.palette_size))
+        } else if self.palette_from_target {
+            ColorModel::Palette(palette::extract(target, self.palette_size))
+        } else {
+            ColorModel::Free
+        }
+    }
+
+    /// 按照背景相关的设置, 在 target 的尺寸上初始化一个空白个体
+    pub fn new_individual(&self, target: &Canvas) -> Individual {
+        let mut bg_color = match self.bg_mode {
+            BgMode::Fixed => self.bg_color,
+            BgMode::Mean => target.mean_color(),
+            BgMode::Median => target.median_color(),
+            BgMode::Dominant => target.dominant_color(),
+        };
+        if self.color_mode == ColorMode::Gray {
+            let (r, g, b) = bg_color;
+            let y = 0.299 * r + 0.587 * g + 0.114 * b;
+            bg_color = (y, y, y);
+        }
+        let mut ind = Individual::new(target.x_height, target.y_width, bg_color);
+        if self.transparent_bg {
+            ind.set_bg_alpha(0.0);
+        }
+        ind
+    }
+
+    /// 检查各项取值是否合法, 不合法则 panic
+    pub fn validate(&self) {
+        if let Err(message) = self.check() {
+            panic!("{}", message);
+        }
+    }
+
+    /// 检查各项取值是否合法, 不合法时返回错误信息
+    pub fn check(&self) -> Result<(), String> {
+        // 条件不成立时返回错误信息
+        macro_rules! ensure {
+            ($cond:expr, $msg:expr) => {{ let ok: bool = $cond; if !ok { return Err($msg.to_string()) } }};
+        }
+        ensure!(self.max_dim.is_none_or(|max_dim| max_dim >= 1), "max_dim 至少为 1!");
+        ensure!(self.crop.is_none_or(|[_, _, width, height]| width >= 1 && height >= 1), "裁剪区域不能为空!");
+        ensure!(self.crop.is_none_or(|[left, top, width, height]| left.checked_add(width).is_some() && top.checked_add(height).is_some()), "裁剪区域超出了图片范围!");
+        ensure!(self.palette_size >= 1, "palette_size 至少为 1!");
+        ensure!(self.pop_size >= 1, "pop_size 至少为 1!");
+        ensure!(self.prop_amount >= 1, "prop_amount 至少为 1!");
+        ensure!(self.n_guard <= self.pop_size, "n_guard 不能超过 pop_size!");
+        ensure!(self.pr_triangle + self.pr_circle + self.pr_rectangle > 0.0, "这三个不能全为 0!");
+        ensure!(self.pr_triangle >= 0.0 && self.pr_circle >= 0.0 && self.pr_rectangle >= 0.0, "概率权重不能为负数!");
+        ensure!(self.adapt_window.is_none_or(|window| window >= 1), "adapt_window 至少为 1!");
+        if let Selection::Tournament { size } = self.selection {
+            ensure!(size >= 1, "锦标赛规模至少为 1!");
+        }
+        ensure!(self.novelty_weight >= 0.0, "novelty_weight 不能为负数!");
+        ensure!(self.novelty_k >= 1, "novelty_k 至少为 1!");
+        ensure!(self.pyramid.windows(2).all(|w| w[0].0 < w[1].0), "pyramid 必须按轮数严格递增!");
+        ensure!(self.pyramid.iter().all(|&(_, scale)| scale > 0.0 && scale <= 1.0), "pyramid 的缩放倍数必须在 (0, 1] 之间!");
+        ensure!(self.n_islands >= 1, "n_islands 至少为 1!");
+        ensure!(self.migration_interval >= 1, "migration_interval 至少为 1!");
+        ensure!(self.n_migrants <= self.pop_size, "n_migrants 不能超过 pop_size!");
+        ensure!(self.pr_remove_shape.is_none_or(|pr| (0.0..=1.0).contains(&pr)), "pr_remove_shape 必须在 [0, 1] 之间!");
+        ensure!((0.0..=1.0).contains(&self.pr_mutate_bg), "pr_mutate_bg 必须在 [0, 1] 之间!");
+        ensure!(self.pareto_pop_size >= 2, "pareto_pop_size 至少为 2!");
+        ensure!(self.pareto_export_interval >= 1, "pareto_export_interval 至少为 1!");
+        match self.save_schedule {
+            SaveSchedule::Every { n } => ensure!(n >= 1, "保存间隔 n 至少为 1!"),
+            SaveSchedule::Improvement { ratio } => ensure!((0.0..1.0).contains(&ratio), "ratio 必须在 [0, 1) 之间!"),
+            SaveSchedule::Interval { seconds } => ensure!(seconds >= 0.0, "seconds 不能为负数!"),
+            SaveSchedule::LogSpaced { per_decade } => ensure!(per_decade >= 1, "per_decade 至少为 1!"),
+            SaveSchedule::Cascade => {}
+        }
+        ensure!(!self.filename.is_empty(), "filename 不能为空!");
+        ensure!(self.max_generations.is_none_or(|n| n >= 1), "max_generations 至少为 1!");
+        ensure!(self.max_seconds.is_none_or(|secs| secs > 0.0), "max_seconds 必须为正数!");
+        ensure!(self.patience.is_none_or(|n| n >= 1), "patience 至少为 1!");
+        ensure!(self.jobs >= 1, "jobs 至少为 1!");
+        ensure!(self.temporal_weight >= 0.0, "temporal_weight 不能为负数!");
+        if let Some(timelapse) = &self.timelapse {
+            timelapse.validate();
+        }
+        Ok(())
+    }
+
+    /// 用 JSON 对象 patch 修改 TUNABLE_FIELDS 中的若干参数, 返回新的配置. 含有其他字段或修改后不合法时返回错误信息
+    pub fn patched(&self, patch: serde_json::Value) -> Result<Config, String> {
+        let fields = patch.as_object().ok_or("需要一个 JSON 对象")?;
+        if let Some(key) = fields.keys().find(|key| !Config::TUNABLE_FIELDS.contains(&key.as_str())) {
+            return Err(format!("参数 {} 不能在运行中修改", key));
+        }
+        let mut json = serde_json::to_value(self).unwrap();
+        merge_json(&mut json, patch);
+        let config: Config = serde_json::from_value(json).map_err(|e| format!("配置有误: {}", e))?;
+        config.check()?;
+        Ok(config)
+    }
+
+    /// 是否设置了任何一个停止条件 (不计 Ctrl-C)
+    pub fn has_stop_criteria(&self) -> bool {
+        self.max_generations.is_some() || self.max_seconds.is_some() || self.target_fitness.is_some()
+            || self.max_shapes.is_some() || self.patience.is_some()
+    }
+
+    /// 按照停止条件, 第 gen 轮结束后是否应当停止. 返回停止的原因.
+    /// elapsed 为已经演化的时长, best 为当前最优个体, stagnant_gens 为最优适应度连续没有改进的轮数
+    pub fn stop_reason(&self, gen: usize, elapsed: std::time::Duration, best: &Individual, stagnant_gens: usize) -> Option<String> {
+        if self.max_generations.is_some_and(|n| gen >= n) {
+            Some(format!("达到了 {} 轮", gen))
+        } else if self.max_seconds.is_some_and(|secs| elapsed.as_secs_f32() >= secs) {
+            Some(format!("演化了 {:.1} 秒", elapsed.as_secs_f32()))
+        } else if self.target_fitness.is_some_and(|fitness| best.get_fitness() <= fitness) {
+            Some(format!("适应度达到了 {}", best.get_fitness()))
+        } else if self.max_shapes.is_some_and(|n| best.n_shapes() >= n) {
+            Some(format!("图形数目达到了 {}", best.n_shapes()))
+        } else if self.patience.is_some_and(|n| stagnant_gens >= n) {
+            Some(format!("连续 {} 轮没有改进", stagnant_gens))
+        } else {
+            None
+        }
+    }
+
+    /// 按照 filename 模板, 生成第 gen 轮的个体 ind 的保存文件名 (不含扩展名)
+    pub fn file_stem(&self, gen: usize, ind: &Individual) -> String {
+        self.filename
+            .replace("{gen}", &gen.to_string())
+            .replace("{fitness}", &format!("{:.4}", ind.get_fitness()))
+            .replace("{n_shapes}", &ind.n_shapes().to_string())
+    }
+
+    /// 自适应变异剧烈程度的调整窗口 (见 adapt_window)
+    pub fn adapt_window(&self) -> usize {
+        self.adapt_window.unwrap_or(10 * self.pop_size * self.prop_amount)
+    }
+
+    /// 按照 pyramid 日程, 第 gen 轮使用的分辨率缩放倍数 (第一项之前也使用第一项的倍数)
+    pub fn scale_at(&self, gen: usize) -> f32 {
+        self.pyramid.iter()
+            .rev()
+            .find(|&&(start, _)| start <= gen)
+            .or(self.pyramid.first())
+            .map_or(1.0, |&(_, scale)| scale)
+    }
+}
+
+
+/// 从命令行参数构造 T. 先取 T 的默认值, 再用 --config 指定的 JSON 文件 (若有) 覆盖, 最后用其余的 --key value 逐项覆盖
+pub fn parse_args<T: Serialize + de::DeserializeOwned + Default>(args: &[String]) -> T {
+    let mut json = serde_json::to_value(T::default()).unwrap();
+    if let Some(i) = args.iter().position(|arg| arg == "--config") {
+        let path = args.get(i + 1).expect("--config 后需要给出文件路径");
+        let file: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap())
+            .expect("配置文件不是合法的 JSON!");
+        merge_json(&mut json, file);
+    }
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let key = arg.strip_prefix("--").unwrap_or_else(|| panic!("无法识别的参数 {}", arg));
+        let value = iter.next().unwrap_or_else(|| panic!("--{} 后需要给出取值", key));
+        if key == "config" { continue }
+        // 能按 JSON 解析的取值 (数字、布尔、数组等) 按 JSON 处理, 否则视为字符串
+        let value = serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.clone()));
+        merge_json(&mut json, serde_json::json!({ key: value }));
+    }
+    serde_json::from_value(json).unwrap_or_else(|e| panic!("配置有误: {}", e))
+}
+
+
+/// 用 patch 中的各个字段覆盖 base 中的同名字段. 每个字段整体替换而不递归合并,
+/// 否则 {"every": {..}} 与 {"interval": {..}} 这样的枚举取值会合并成两个键的对象
+fn merge_json(base: &mut serde_json::Value, patch: serde_json::Value) {
+    match (base, patch) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                base.insert(key, value);
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_switches_between_object_variants() {
+        let cfg = Config { save_schedule: SaveSchedule::Every { n: 5 }, ..Config::default() };
+        let patched = cfg.patched(serde_json::json!({ "save_schedule": { "improvement": { "ratio": 0.01 } } })).unwrap();
+        assert_eq!(patched.save_schedule, SaveSchedule::Improvement { ratio: 0.01 });
+
+        // 配置文件与命令行的合并也一样
+        let mut json = serde_json::to_value(&cfg).unwrap();
+        merge_json(&mut json, serde_json::json!({ "save_schedule": { "interval": { "seconds": 60.0 } } }));
+        let merged: Config = serde_json::from_value(json).unwrap();
+        assert_eq!(merged.save_schedule, SaveSchedule::Interval { seconds: 60.0 });
+    }
+}
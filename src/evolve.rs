@@ -0,0 +1,268 @@
+use std::cmp::min;
+use crate::config::{Config, Selection};
+use crate::util::*;
+
+
+/// 一轮演化的统计数据
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StepStats {
+    pub n_evals: usize,         // 计算适应度的次数
+    pub n_mutated: usize,       // 发生了变异的子代数目
+    pub n_improved: usize,      // 其中优于亲代的数目
+}
+impl StepStats {
+    /// 变异的接受率: 优于亲代的子代占发生了变异的子代的比例
+    pub fn acceptance_rate(&self) -> f32 {
+        if self.n_mutated == 0 { 0.0 } else { self.n_improved as f32 / self.n_mutated as f32 }
+    }
+
+    /// 累加另一份统计
+    pub fn add(&mut self, other: &StepStats) {
+        self.n_evals += other.n_evals;
+        self.n_mutated += other.n_mutated;
+        self.n_improved += other.n_improved;
+    }
+}
+
+
+/// 一个独立演化的种群. 个体按适应度从优到劣排列
+pub struct Population {
+    pub individuals: Vec<Individual>,
+    pub last_stats: StepStats,      // 最近一轮的统计数据
+    adaptive_amp: AdaptiveAmp,
+    anchor: Option<(Individual, f32)>,  // 时间连贯性的参照个体和惩罚权重: 适应度额外加上 权重 × 与参照个体的基因差异
+}
+impl Population {
+    /// 创建最初的随机种群
+    pub fn new(target: &Canvas, cfg: &Config) -> Population {
+        let mut individuals: Vec<Individual> = Vec::new();
+        for _ in 0..cfg.pop_size {
+            let mut ind = cfg.new_individual(target);
+            ind.calc_fitness(target);
+            individuals.push(ind);
+        }
+        Population {
+            individuals,
+            last_stats: StepStats::default(),
+            adaptive_amp: AdaptiveAmp::new(cfg.mutate_amp, cfg.adapt_window()),
+            anchor: None,
+        }
+    }
+
+    /// 由已有的个体 (如检查点中保存的) 组成种群, 缩放到目标图片的尺寸上并重新计算适应度
+    pub fn from_individuals(individuals: Vec<Individual>, target: &Canvas, cfg: &Config) -> Population {
+        assert!(!individuals.is_empty(), "种群不能为空!");
+        let mut population = Population {
+            individuals,
+            last_stats: StepStats::default(),
+            adaptive_amp: AdaptiveAmp::new(cfg.mutate_amp, cfg.adapt_window()),
+            anchor: None,
+        };
+        population.rescale(target);
+        population
+    }
+
+    /// 当前最优的个体
+    pub fn best(&self) -> &Individual {
+        self.individuals.first().unwrap()
+    }
+
+    /// 当前使用的变异剧烈程度
+    pub fn amp(&self, cfg: &Config) -> f32 {
+        if cfg.adaptive_amp { self.adaptive_amp.amp() } else { cfg.mutate_amp }
+    }
+
+    /// 演化一轮: 产仔、变异、计算适应度、择优保留 pop_size 个. 图形颜色受 colors 约束
+    pub fn step(&mut self, target: &Canvas, cfg: &Config, colors: &ColorModel) {
+        let last_population = &self.individuals;
+        let mut new_generation: Vec<Individual> = Vec::with_capacity(last_population.len() * cfg.prop_amount + cfg.n_guard);
+        let mut parent_fitness: Vec<Option<f32>> = Vec::with_capacity(last_population.len() * cfg.prop_amount);   // 发生了变异的子代对应的亲代适应度
+        let amp = self.amp(cfg);
+
+        // 每个个体产生 prop_amount 个变异幼崽
+        for ind in last_population {
+            for _ in 0..cfg.prop_amount {
+                let (child, mutated) = spawn_child(ind, target, cfg, amp, colors);
+                parent_fitness.push(if mutated { Some(ind.get_fitness()) } else { None });
+                new_generation.push(child);
+            }
+        }
+
+        // 把保底的 n_guard 个上一轮个体也添加进去
+        for ind in &last_population[0..min(cfg.n_guard, last_population.len())] {
+            new_generation.push(ind.clone());
+        }
+
+        // 计算所有个体的适应度
+        let mut stats = StepStats::default();
+        for ind in &mut new_generation {
+            stats.n_evals += ind.fitness_unknown() as usize;
+            self.evaluate(ind, target);
+        }
+
+        // 统计变异的成功率, 调整变异剧烈程度
+        for (child, parent_fitness) in new_generation.iter().zip(&parent_fitness) {
+            if let Some(parent_fitness) = parent_fitness {
+                let success = child.get_fitness() < *parent_fitness;
+                stats.n_mutated += 1;
+                stats.n_improved += success as usize;
+                if cfg.adaptive_amp {
+                    self.adaptive_amp.record(success);
+                }
+            }
+        }
+        self.last_stats = stats;
+
+        // 按照选择策略留下 pop_size 个, 再按照适应度进行排序
+        self.individuals = select(new_generation, cfg.pop_size, cfg);
+        sort_by_fitness(&mut self.individuals);
+    }
+
+    /// 将全部个体缩放到新的目标图片的尺寸上, 并重新计算适应度
+    pub fn rescale(&mut self, target: &Canvas) {
+        self.individuals = self.individuals.iter()
+            .map(|ind| {
+                let mut ind = ind.rescaled(target.x_height, target.y_width);
+                self.evaluate(&mut ind, target);
+                ind
+            })
+            .collect();
+        sort_by_fitness(&mut self.individuals);
+    }
+
+    /// 设置时间连贯性的参照个体 (如视频上一帧的最优个体) 和惩罚权重, 为 None 时取消.
+    /// 之后需要重新计算适应度, 一般紧接着调用 rescale
+    pub fn set_anchor(&mut self, anchor: Option<(Individual, f32)>) {
+        self.anchor = anchor;
+    }
+
+    /// 计算个体的适应度 (已经计算过的不重复计算), 有参照个体时加上时间连贯性惩罚
+    fn evaluate(&self, ind: &mut Individual, target: &Canvas) {
+        if !ind.fitness_unknown() { return }
+        ind.calc_fitness(target);
+        if let Some((anchor, weight)) = &self.anchor {
+            ind.add_penalty(weight * ind.distance(anchor));
+        }
+    }
+
+    /// 修剪最优个体中多余的图形 (见 Individual::prune), 返回移除的图形数目
+    pub fn prune_best(&mut self, target: &Canvas) -> usize {
+        let n_removed = self.individuals[0].prune(target);
+        sort_by_fitness(&mut self.individuals);
+        n_removed
+    }
+
+    /// 接收外来个体: 与原有个体合并后保留最优的若干个 (种群大小不变), 因此原有的优秀个体不会被挤掉
+    pub fn receive(&mut self, migrants: Vec<Individual>) {
+        let size = self.individuals.len();
+        self.individuals.extend(migrants);
+        sort_by_fitness(&mut self.individuals);
+        self.individuals.truncate(size);
+    }
+}
+
+
+/// 按照适应度从优到劣排序
+pub fn sort_by_fitness(individuals: &mut [Individual]) {
+    individuals.sort_by(|ind1, ind2| {
+        let f1 = ind1.get_fitness();
+        let f2 = ind2.get_fitness();
+        f1.partial_cmp(&f2).unwrap()
+    });
+}
+
+
+/// 由 parent 产生一个变异幼崽 (适应度尚未计算), 图形颜色受 colors 约束. 第二个返回值表示是否发生了变异
+pub fn spawn_child(parent: &Individual, target: &Canvas, cfg: &Config, amp: f32, colors: &ColorModel) -> (Individual, bool) {
+    let canvas_size = min(target.x_height, target.y_width);
+    let mut child = parent.clone();
+    // 对自己至多 mutate_ratio 的图形进行突变
+    let mutate_amount = random::randint(0, (parent.n_shapes() as f32 * cfg.mutate_ratio) as usize + 1);
+    for _ in 0..mutate_amount {
+        let which = random::randint(0usize, parent.n_shapes());
+        child.mutate_shape(which, canvas_size, amp, colors);
+        if cfg.optimal_color { child.solve_color(which, target, colors); }
+    }
+    // 以 pr_mutate_bg 的概率令背景色变异
+    let mut mutated = mutate_amount > 0;
+    if random::uniform(0., 1.) < cfg.pr_mutate_bg {
+        child.mutate_bg(amp, colors);
+        mutated = true;
+    }
+    // 以 pr_remove_shape 的概率移除一个图形
+    if child.n_shapes() > 0 && random::uniform(0., 1.) < cfg.pr_remove_shape.unwrap_or(0.0) {
+        child.remove_shape(random::randint(0usize, child.n_shapes()));
+    }
+    // 以 pr_add_shape 的概率新增一个图形
+    if random::uniform(0., 1.) < cfg.pr_add_shape {     // 决定是否新增一个图形
+        let shape_chosen = random::weighted_choice(
+            &["triangle", "circle", "rectangle"],           // 按照权重随机抽取一个图形
+            &[cfg.pr_triangle, cfg.pr_circle, cfg.pr_rectangle]);
+        child.add_shape(shape_chosen, colors);
+        if cfg.optimal_color { child.solve_color(child.n_shapes() - 1, target, colors); }
+    }
+    (child, mutated)
+}
+
+
+/// 按照 cfg.selection 从候选个体中无放回地选出 n 个 (适应度最优者总是入选)
+fn select(candidates: Vec<Individual>, n: usize, cfg: &Config) -> Vec<Individual> {
+    if candidates.len() <= n { return candidates }
+
+    // 计算得分 (越小越好), 必要时加入多样性奖励
+    let mut scores: Vec<f32> = candidates.iter().map(|ind| ind.get_fitness()).collect();
+    if cfg.novelty_weight > 0.0 {
+        for (i, score) in scores.iter_mut().enumerate() {
+            *score -= cfg.novelty_weight * novelty(&candidates, i, cfg.novelty_k);
+        }
+    }
+
+    // 按得分排序后的下标. 适应度最优者 (不计多样性奖励) 直接入选
+    let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+    remaining.sort_by(|&i, &j| scores[i].partial_cmp(&scores[j]).unwrap());
+    let elite = (0..candidates.len())
+        .min_by(|&i, &j| candidates[i].get_fitness().partial_cmp(&candidates[j].get_fitness()).unwrap())
+        .unwrap();
+    remaining.retain(|&i| i != elite);
+    let mut chosen = vec![elite];
+
+    while chosen.len() < n {
+        // pos 是 remaining 中的位置, remaining 始终按得分从优到劣排列
+        let pos = match cfg.selection {
+            Selection::Truncation => 0,
+            Selection::Tournament { size } => {
+                (0..size).map(|_| random::randint(0, remaining.len())).min().unwrap()
+            }
+            Selection::Roulette => {
+                let worst = scores[*remaining.last().unwrap()];
+                let best = scores[remaining[0]];
+                let eps = f32::max((worst - best) * 0.01, 1e-6);   // 保证最差的个体也有机会
+                let weights: Vec<f32> = remaining.iter().map(|&i| worst - scores[i] + eps).collect();
+                random::weighted_choice(&(0..remaining.len()).collect::<Vec<_>>(), &weights)
+            }
+            Selection::Rank => {
+                let len = remaining.len();
+                let weights: Vec<f32> = (0..len).map(|rank| (len - rank) as f32).collect();
+                random::weighted_choice(&(0..len).collect::<Vec<_>>(), &weights)
+            }
+        };
+        chosen.push(remaining.remove(pos));
+    }
+
+    // 按下标取出选中的个体
+    let mut slots: Vec<Option<Individual>> = candidates.into_iter().map(Some).collect();
+    chosen.iter().map(|&i| slots[i].take().unwrap()).collect()
+}
+
+
+/// 第 i 个个体的新颖度: 与其余个体中最近的 k 个的平均基因差异
+fn novelty(individuals: &[Individual], i: usize, k: usize) -> f32 {
+    let mut distances: Vec<f32> = individuals.iter().enumerate()
+        .filter(|(j, _)| *j != i)
+        .map(|(_, other)| individuals[i].distance(other))
+        .collect();
+    if distances.is_empty() { return 0.0 }
+    distances.sort_by(|d1, d2| d1.partial_cmp(d2).unwrap());
+    let k = min(k, distances.len());
+    distances[..k].iter().sum::<f32>() / k as f32
+}
@@ -0,0 +1,582 @@
+//! 基因文件 (*.json) 的格式. 当前为第 2 版:
+//!
+//! ```json
+//! {
+//!   "version": 2,
+//!   "metadata": {                                 // 可省略, 其中各项也都可省略
+//!     "generation": 5000,                         // 保存时的演化轮数
+//!     "timestamp": "2026-10-18T21:30:00+08:00",   // 保存时刻 (RFC 3339)
+//!     "seed": 12345,                              // 随机数种子, 以相同的配置和种子可以复现这次运行
+//!     "target_hash": "fnv1a64:9c3f...",           // 目标图片文件的散列
+//!     "config": { ... }                           // 完整的 Config
+//!   },
+//!   "height": 352, "width": 298,                  // 画布尺寸 (像素)
+//!   "bg_color": [255.0, 255.0, 255.0], "bg_alpha": 1.0,
+//!   "shapes": [                                   // 按 z 序从下到上
+//!     {"type": "triangle", "p1": {"x": .., "y": ..}, "p2": .., "p3": .., "color": {"r": .., "g": .., "b": .., "a": ..}},
+//!     {"type": "circle", "center": {"x": .., "y": ..}, "radius": 12.5, "color": ..},
+//!     {"type": "rectangle", "p1": .., "p2": .., "color": {"gray": .., "a": ..}}
+//!   ]
+//! }
+//! ```
+//! 坐标 x 向下、y 向右 (与 Canvas 一致), 颜色分量取 [0, 255], 不透明度取 [0, 1]. 适应度不保存, 读取后需重新计算.
+//! 灰色 (r = g = b, 如灰度模式) 的图形颜色只保存一个通道 {"gray": .., "a": ..}.
+//!
+//! 第 1 版即没有 version 字段的旧文件 (serde 直接输出的 Individual): 尺寸为 env_height / env_width,
+//! 图形为 {"type": "Circle", "data": {..., "radius": [r]}}, 并带有 fitness. 读取时先逐版迁移到当前版本.
+//!
+//! 同样的内容还可以保存为紧凑的二进制文件 (*.eag), 见 encode_binary; 检查点也可以保存为二进制, 见 encode_checkpoint
+
+use std::io::{Read, Write};
+use serde::*;
+use serde_json::{Map, Value};
+use crate::island::Checkpoint;
+use crate::util::Individual;
+
+
+/// 当前的基因文件格式版本
+pub const GENOME_VERSION: u64 = 2;
+
+
+/// 基因文件中记录的来历, 各项均可缺失
+#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenomeMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<usize>,      // 保存时的演化轮数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,      // 保存时刻 (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,              // 随机数种子
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_hash: Option<String>,    // 目标图片文件的散列, 见 hash_bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<Value>,          // 完整的配置
+}
+
+
+/// 将任意版本的基因文件 (已解析为 JSON) 逐版迁移到当前版本
+pub fn migrate(mut genome: Value) -> Result<Value, String> {
+    let version = match genome.get("version") {
+        None => 1,
+        Some(version) => version.as_u64().ok_or("version 必须是正整数")?,
+    };
+    if version > GENOME_VERSION {
+        return Err(format!("基因文件的版本 ({}) 比本程序支持的版本 ({}) 新, 请升级程序", version, GENOME_VERSION));
+    }
+    if version < 2 {
+        genome = v1_to_v2(genome)?;
+    }
+    Ok(genome)
+}
+
+
+/// 第 1 版 → 第 2 版
+fn v1_to_v2(genome: Value) -> Result<Value, String> {
+    let Value::Object(mut old) = genome else { return Err("基因文件必须是 JSON 对象".to_string()) };
+    let mut take = |key: &str| old.remove(key).ok_or_else(|| format!("第 1 版的基因文件缺少 {}", key));
+    let (height, width, bg_color, shapes) = (take("env_height")?, take("env_width")?, take("bg_color")?, take("shapes")?);
+    let bg_alpha = old.remove("bg_alpha").unwrap_or(Value::from(1.0));    // 更早的文件没有 bg_alpha
+
+    let Value::Array(shapes) = shapes else { return Err("shapes 必须是数组".to_string()) };
+    let shapes = shapes.into_iter().map(|shape| {
+        // {"type": "Circle", "data": {..., "radius": [r]}} → {"type": "circle", ..., "radius": r}
+        let type_name = shape.get("type").and_then(Value::as_str).ok_or("图形缺少 type")?.to_lowercase();
+        let Some(Value::Object(data)) = shape.get("data").cloned() else { return Err("图形缺少 data".to_string()) };
+        let mut new = Map::new();
+        new.insert("type".to_string(), Value::from(type_name));
+        for (key, value) in data {
+            let value = match (key.as_str(), value) {
+                ("radius", Value::Array(mut radius)) if radius.len() == 1 => radius.remove(0),
+                (_, value) => value,
+            };
+            new.insert(key, value);
+        }
+        Ok(Value::Object(new))
+    }).collect::<Result<Vec<Value>, String>>()?;
+
+    Ok(serde_json::json!({
+        "version": 2,
+        "height": height,
+        "width": width,
+        "bg_color": bg_color,
+        "bg_alpha": bg_alpha,
+        "shapes": shapes,
+    }))
+}
+
+
+/// 64 位 FNV-1a 散列, 用于记录目标图片 (如 "fnv1a64:cbf29ce484222325")
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("fnv1a64:{:016x}", hash)
+}
+
+
+/// convert 子命令的设置
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConvertOptions {
+    pub compress: bool,             // 二进制文件是否压缩
+    pub target: Option<String>,     // 目标图片, 给出时比较转换前后的适应度 (按个体元数据中原运行的设置裁剪和比对)
+    pub tolerance: f32,             // 转为二进制时允许的适应度相对变化
+}
+impl Default for ConvertOptions {
+    fn default() -> ConvertOptions {
+        ConvertOptions {
+            compress: true,
+            target: None,
+            tolerance: 0.01,
+        }
+    }
+}
+
+
+/// 二进制基因文件开头的标识
+const BINARY_MAGIC: &[u8; 4] = b"EAGB";
+
+/// 二进制检查点文件开头的标识
+const CHECKPOINT_MAGIC: &[u8; 4] = b"EAGC";
+
+/// 当前的二进制格式版本 (基因文件和检查点共用)
+const BINARY_VERSION: u8 = 1;
+
+/// 二进制格式中各类图形的坐标字段 (按顺序), 以及图形类别的编号 (即下标)
+const BINARY_SHAPES: [(&str, &[&str]); 3] = [
+    ("triangle", &["p1", "p2", "p3"]),
+    ("circle", &["center"]),
+    ("rectangle", &["p1", "p2"]),
+];
+
+
+/// 坐标的 16 位量化: 在 [lo, hi] 上均匀取 65536 个值, 误差不超过 (hi - lo) / 131070
+#[derive(Copy, Clone)]
+struct Quantizer {
+    lo: f32,
+    hi: f32,
+}
+impl Quantizer {
+    /// 恰好覆盖 values 的量化区间
+    fn covering(values: impl Iterator<Item = f32>) -> Quantizer {
+        let (lo, hi) = values.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        if lo > hi { Quantizer { lo: 0.0, hi: 0.0 } } else { Quantizer { lo, hi } }
+    }
+
+    fn encode(self, v: f32) -> u16 {
+        if self.hi > self.lo { ((v - self.lo) / (self.hi - self.lo) * 65535.0).round() as u16 } else { 0 }
+    }
+
+    fn decode(self, code: u16) -> f32 {
+        self.lo + code as f32 / 65535.0 * (self.hi - self.lo)
+    }
+
+    /// 量化误差的上界
+    fn max_error(self) -> f32 {
+        (self.hi - self.lo) / 131070.0
+    }
+}
+
+
+/// JSON 中的数值, 不是有限值时报错 (serde_json 将 NaN 和无穷输出为 null)
+fn number(value: &Value) -> Result<f32, String> {
+    value.as_f64().map(|v| v as f32).filter(|v| v.is_finite()).ok_or_else(|| "基因中有不是有限值的参数".to_string())
+}
+
+
+/// 图形的各个点, 按 BINARY_SHAPES 中的顺序
+fn shape_points(shape: &Value) -> Vec<&Value> {
+    let fields = BINARY_SHAPES.iter().find(|(kind, _)| shape["type"] == *kind).map_or(&[][..], |(_, fields)| fields);
+    fields.iter().map(|field| &shape[field]).collect()
+}
+
+
+/// 恰好覆盖全部图形的 x, y 坐标和半径的三个量化区间
+fn quantizers(shapes: &[Value]) -> Result<(Quantizer, Quantizer, Quantizer), String> {
+    let (mut xs, mut ys, mut radii) = (Vec::new(), Vec::new(), Vec::new());
+    for shape in shapes {
+        for point in shape_points(shape) {
+            xs.push(number(&point["x"])?);
+            ys.push(number(&point["y"])?);
+        }
+        if shape["type"] == "circle" {
+            radii.push(number(&shape["radius"])?);
+        }
+    }
+    Ok((Quantizer::covering(xs.into_iter()), Quantizer::covering(ys.into_iter()), Quantizer::covering(radii.into_iter())))
+}
+
+
+/// 将个体编码为二进制基因文件 (小端序):
+///   "EAGB", 格式版本 (u8), 是否压缩 (u8), 之后的内容在压缩时为 zlib 流:
+///   height, width (u32); 背景 r, g, b, a (u8);
+///   x, y, radius 三个量化区间的 lo, hi (f32 × 6); 图形数目 (u32);
+///   每个图形: 类别 (u8, 0 三角形 / 1 圆 / 2 矩形), 各点的 x, y (u16), 圆的半径 (u16), 颜色 r, g, b, a (u8);
+///   元数据 JSON 的字节数 (u32, 0 表示没有) 和内容.
+/// 坐标按各自的区间量化为 16 位, 颜色和不透明度量化为 8 位
+pub fn encode_binary(ind: &Individual, metadata: Option<&GenomeMetadata>, compress: bool) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    write_individual(ind, &mut body)?;
+    let metadata = metadata.map(|metadata| serde_json::to_vec(metadata).unwrap()).unwrap_or_default();
+    body.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    body.extend_from_slice(&metadata);
+    Ok(frame(BINARY_MAGIC, &body, compress))
+}
+
+
+/// 将检查点编码为二进制检查点文件 (小端序):
+///   "EAGC", 格式版本 (u8), 是否压缩 (u8), 之后的内容在压缩时为 zlib 流:
+///   已完成的轮数 (u64); 是否有种子 (u8) 和种子 (u64); 岛屿数目 (u32);
+///   每个岛屿: 个体数目 (u32), 各个体 (同二进制基因文件中从 height 到最后一个图形的部分).
+/// 个体的参数同样经过量化, 恢复后的适应度与保存时略有差别
+pub fn encode_checkpoint(checkpoint: &Checkpoint, compress: bool) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&(checkpoint.gen as u64).to_le_bytes());
+    body.push(checkpoint.seed.is_some() as u8);
+    body.extend_from_slice(&checkpoint.seed.unwrap_or(0).to_le_bytes());
+    body.extend_from_slice(&(checkpoint.islands.len() as u32).to_le_bytes());
+    for island in &checkpoint.islands {
+        body.extend_from_slice(&(island.len() as u32).to_le_bytes());
+        for ind in island {
+            write_individual(ind, &mut body)?;
+        }
+    }
+    Ok(frame(CHECKPOINT_MAGIC, &body, compress))
+}
+
+
+/// 加上文件头: 标识, 格式版本, 是否压缩. 压缩时 body 写为 zlib 流
+fn frame(magic: &[u8; 4], body: &[u8], compress: bool) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.push(BINARY_VERSION);
+    bytes.push(compress as u8);
+    if compress {
+        let mut encoder = flate2::write::ZlibEncoder::new(bytes, flate2::Compression::best());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    } else {
+        bytes.extend_from_slice(body);
+        bytes
+    }
+}
+
+
+/// 写入一个个体: 尺寸、背景、量化区间和全部图形
+fn write_individual(ind: &Individual, body: &mut Vec<u8>) -> Result<(), String> {
+    let genome = serde_json::to_value(ind).unwrap();
+    let shapes = genome["shapes"].as_array().unwrap();
+    let (qx, qy, qr) = quantizers(shapes)?;
+
+    let channel = |v: f32, max: f32| (v / max * 255.0).round().clamp(0.0, 255.0) as u8;
+    body.extend_from_slice(&(genome["height"].as_u64().unwrap() as u32).to_le_bytes());
+    body.extend_from_slice(&(genome["width"].as_u64().unwrap() as u32).to_le_bytes());
+    for c in genome["bg_color"].as_array().unwrap() {
+        body.push(channel(number(c)?, 255.0));
+    }
+    body.push(channel(number(&genome["bg_alpha"])?, 1.0));
+    for q in [qx, qy, qr] {
+        body.extend_from_slice(&q.lo.to_le_bytes());
+        body.extend_from_slice(&q.hi.to_le_bytes());
+    }
+    body.extend_from_slice(&(shapes.len() as u32).to_le_bytes());
+    for shape in shapes {
+        body.push(BINARY_SHAPES.iter().position(|(kind, _)| shape["type"] == *kind).unwrap() as u8);
+        for point in shape_points(shape) {
+            body.extend_from_slice(&qx.encode(number(&point["x"])?).to_le_bytes());
+            body.extend_from_slice(&qy.encode(number(&point["y"])?).to_le_bytes());
+        }
+        if shape["type"] == "circle" {
+            body.extend_from_slice(&qr.encode(number(&shape["radius"])?).to_le_bytes());
+        }
+        let color = &shape["color"];
+        for (key, max) in [("r", 255.0), ("g", 255.0), ("b", 255.0), ("a", 1.0)] {
+            let key = if key != "a" && color.get("gray").is_some() { "gray" } else { key };
+            body.push(channel(number(&color[key])?, max));
+        }
+    }
+    Ok(())
+}
+
+
+/// 按顺序读取二进制内容
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+impl ByteReader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        if self.bytes.len() < N { return Err("二进制文件不完整".to_string()) }
+        let (head, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(head.try_into().unwrap())
+    }
+    fn u8(&mut self) -> Result<u8, String> { Ok(self.take::<1>()?[0]) }
+    fn u16(&mut self) -> Result<u16, String> { Ok(u16::from_le_bytes(self.take()?)) }
+    fn u32(&mut self) -> Result<u32, String> { Ok(u32::from_le_bytes(self.take()?)) }
+    fn u64(&mut self) -> Result<u64, String> { Ok(u64::from_le_bytes(self.take()?)) }
+    fn f32(&mut self) -> Result<f32, String> { Ok(f32::from_le_bytes(self.take()?)) }
+}
+
+
+/// 内容是否为二进制基因文件
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(BINARY_MAGIC)
+}
+
+
+/// 内容是否为二进制检查点文件
+pub fn is_binary_checkpoint(bytes: &[u8]) -> bool {
+    bytes.starts_with(CHECKPOINT_MAGIC)
+}
+
+
+/// 解码 encode_binary 编码的二进制基因文件
+pub fn decode_binary(bytes: &[u8]) -> Result<(Individual, Option<GenomeMetadata>), String> {
+    if !is_binary(bytes) { return Err("不是二进制基因文件".to_string()) }
+    let body = unframe(bytes)?;
+    let mut reader = ByteReader { bytes: &body };
+    let ind = read_individual(&mut reader)?;
+    let metadata_len = reader.u32()? as usize;
+    if reader.bytes.len() < metadata_len { return Err("二进制文件不完整".to_string()) }
+    let metadata = match metadata_len {
+        0 => None,
+        _ => Some(serde_json::from_slice(&reader.bytes[..metadata_len]).map_err(|e| format!("元数据有误: {}", e))?),
+    };
+    Ok((ind, metadata))
+}
+
+
+/// 解码 encode_checkpoint 编码的二进制检查点文件
+pub fn decode_checkpoint(bytes: &[u8]) -> Result<Checkpoint, String> {
+    if !is_binary_checkpoint(bytes) { return Err("不是二进制检查点文件".to_string()) }
+    let body = unframe(bytes)?;
+    let mut reader = ByteReader { bytes: &body };
+    let gen = reader.u64()? as usize;
+    let (has_seed, seed) = (reader.u8()? != 0, reader.u64()?);
+    let n_islands = reader.u32()?;
+    let mut islands = Vec::new();
+    for _ in 0..n_islands {
+        let n_individuals = reader.u32()?;
+        islands.push((0..n_individuals).map(|_| read_individual(&mut reader)).collect::<Result<Vec<_>, _>>()?);
+    }
+    Ok(Checkpoint { gen, seed: has_seed.then_some(seed), islands })
+}
+
+
+/// 检查文件头 (标识已由调用者检查) 并取出 (解压后的) 内容
+fn unframe(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut header = ByteReader { bytes: &bytes[BINARY_MAGIC.len()..] };
+    let version = header.u8()?;
+    if version > BINARY_VERSION {
+        return Err(format!("二进制文件的版本 ({}) 比本程序支持的版本 ({}) 新, 请升级程序", version, BINARY_VERSION));
+    }
+    match header.u8()? {
+        0 => Ok(header.bytes.to_vec()),
+        _ => {
+            let mut body = Vec::new();
+            flate2::read::ZlibDecoder::new(header.bytes).read_to_end(&mut body).map_err(|e| format!("无法解压二进制文件: {}", e))?;
+            Ok(body)
+        }
+    }
+}
+
+
+/// 读取 write_individual 写入的个体
+fn read_individual(reader: &mut ByteReader) -> Result<Individual, String> {
+    let (height, width) = (reader.u32()?, reader.u32()?);
+    let bg: Vec<f32> = (0..4).map(|_| reader.u8().map(|c| c as f32)).collect::<Result<_, _>>()?;
+    let mut quantizer = || -> Result<Quantizer, String> { Ok(Quantizer { lo: reader.f32()?, hi: reader.f32()? }) };
+    let (qx, qy, qr) = (quantizer()?, quantizer()?, quantizer()?);
+    let n_shapes = reader.u32()?;
+    let mut shapes = Vec::new();
+    for _ in 0..n_shapes {
+        let &(kind, fields) = BINARY_SHAPES.get(reader.u8()? as usize).ok_or("未知的图形类别")?;
+        let mut shape = Map::new();
+        shape.insert("type".to_string(), Value::from(kind));
+        for field in fields {
+            let (x, y) = (qx.decode(reader.u16()?), qy.decode(reader.u16()?));
+            shape.insert(field.to_string(), serde_json::json!({ "x": x, "y": y }));
+        }
+        if kind == "circle" {
+            shape.insert("radius".to_string(), Value::from(qr.decode(reader.u16()?)));
+        }
+        let (r, g, b, a) = (reader.u8()? as f32, reader.u8()? as f32, reader.u8()? as f32, reader.u8()? as f32 / 255.0);
+        shape.insert("color".to_string(), serde_json::json!({ "r": r, "g": g, "b": b, "a": a }));
+        shapes.push(Value::Object(shape));
+    }
+
+    let genome = serde_json::json!({
+        "version": GENOME_VERSION,
+        "height": height,
+        "width": width,
+        "bg_color": [bg[0], bg[1], bg[2]],
+        "bg_alpha": bg[3] / 255.0,
+        "shapes": shapes,
+    });
+    serde_json::from_value(genome).map_err(|e| format!("二进制基因有误: {}", e))
+}
+
+
+/// 检查 decoded (由 original 编码再解码得到) 与 original 的结构完全相同, 且每个参数的差异都在量化误差之内
+pub fn verify_round_trip(original: &Individual, decoded: &Individual) -> Result<(), String> {
+    // 量化可能使不同的 r, g, b 变得相同 (于是保存为灰色), 因此先把灰色都展开为 r, g, b 再比较
+    let expand_gray = |mut genome: Value| {
+        for shape in genome["shapes"].as_array_mut().unwrap() {
+            let color = shape["color"].as_object_mut().unwrap();
+            if let Some(gray) = color.remove("gray") {
+                for key in ["r", "g", "b"] {
+                    color.insert(key.to_string(), gray.clone());
+                }
+            }
+        }
+        genome
+    };
+    let (original, decoded) = (expand_gray(serde_json::to_value(original).unwrap()), expand_gray(serde_json::to_value(decoded).unwrap()));
+    let (qx, qy, qr) = quantizers(original["shapes"].as_array().unwrap())?;
+    // 按字段名确定允许的误差; 另加一点余量, 吸收 f32 运算的舍入误差
+    let tolerance = |key: &str| match key {
+        "x" => qx.max_error(),
+        "y" => qy.max_error(),
+        "radius" => qr.max_error(),
+        "a" | "bg_alpha" => 0.5 / 255.0,
+        _ => 0.5,     // 颜色分量
+    } * 1.001 + 1e-4;
+
+    fn walk(a: &Value, b: &Value, key: &str, path: &str, tolerance: &dyn Fn(&str) -> f32) -> Result<(), String> {
+        match (a, b) {
+            (Value::Object(a), Value::Object(b)) => {
+                if a.len() != b.len() { return Err(format!("{} 的字段不同", path)) }
+                a.iter().try_for_each(|(k, v)| walk(v, b.get(k).ok_or(format!("{}.{} 缺失", path, k))?, k, &format!("{}.{}", path, k), tolerance))
+            }
+            (Value::Array(a), Value::Array(b)) => {
+                if a.len() != b.len() { return Err(format!("{} 的长度不同", path)) }
+                a.iter().zip(b).enumerate().try_for_each(|(i, (v, w))| walk(v, w, key, &format!("{}[{}]", path, i), tolerance))
+            }
+            (Value::Number(_), Value::Number(_)) if key != "height" && key != "width" && key != "version" => {
+                let diff = (number(a)? - number(b)?).abs();
+                if diff <= tolerance(key) { Ok(()) } else { Err(format!("{} 相差 {}, 超过了量化误差 {}", path, diff, tolerance(key))) }
+            }
+            _ if a == b => Ok(()),
+            _ => Err(format!("{} 不同: {} ≠ {}", path, a, b)),
+        }
+    }
+    walk(&original, &decoded, "", "genome", &tolerance)
+}
+
+
+/// 读取基因文件 (JSON 的任意版本, 或二进制), 以及其中记录的元数据
+pub fn read_file(path: &str) -> (Individual, Option<GenomeMetadata>) {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| panic!("无法读取基因文件 {}: {}", path, e));
+    if is_binary(&bytes) {
+        decode_binary(&bytes).unwrap_or_else(|message| panic!("{}: {}", path, message))
+    } else {
+        Individual::from_json_with_metadata(&String::from_utf8_lossy(&bytes))
+    }
+}
+
+
+/// 读取检查点文件 (JSON 或二进制)
+pub fn read_checkpoint(path: &str) -> Checkpoint {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| panic!("无法读取检查点文件 {}: {}", path, e));
+    if is_binary_checkpoint(&bytes) {
+        decode_checkpoint(&bytes).unwrap_or_else(|message| panic!("{}: {}", path, message))
+    } else {
+        serde_json::from_slice(&bytes).unwrap_or_else(|e| panic!("检查点文件 {} 有误: {}", path, e))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{random, Canvas, ColorModel};
+
+    /// 三种图形各若干个的随机个体
+    fn sample_individual() -> Individual {
+        sample_individual_with(&ColorModel::Free)
+    }
+
+    /// 颜色受 colors 约束的 sample_individual
+    fn sample_individual_with(colors: &ColorModel) -> Individual {
+        random::seed(7);
+        let mut ind = Individual::new(40, 30, (10.0, 20.0, 30.0));
+        for _ in 0..4 {
+            for kind in ["triangle", "circle", "rectangle"] {
+                ind.add_shape(kind, colors);
+            }
+        }
+        ind
+    }
+
+    /// 各图形的类别 (按 z 序)
+    fn kinds(ind: &Individual) -> Vec<&'static str> {
+        ind.shape_summaries().into_iter().map(|s| s.kind).collect()
+    }
+
+    #[test]
+    fn json_binary_json_round_trip() {
+        let original = Individual::from_json(&sample_individual().to_json());
+        let metadata = GenomeMetadata { generation: Some(42), seed: Some(7), ..Default::default() };
+        for compress in [false, true] {
+            let bytes = encode_binary(&original, Some(&metadata), compress).unwrap();
+            let (decoded, decoded_metadata) = decode_binary(&bytes).unwrap();
+            let decoded = Individual::from_json(&decoded.to_json());
+            verify_round_trip(&original, &decoded).unwrap();
+            assert_eq!(kinds(&decoded), kinds(&original));
+            assert_eq!(decoded_metadata, Some(metadata.clone()));
+            // 再次编码时量化区间不变, 结果完全相同
+            assert_eq!(encode_binary(&decoded, Some(&metadata), compress).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn gray_colors_are_stored_as_one_channel() {
+        let original = sample_individual_with(&ColorModel::Gray);
+        let json = original.to_json();
+        assert!(json.contains("\"gray\"") && !json.contains("\"r\""));
+        let reloaded = Individual::from_json(&json);
+        verify_round_trip(&original, &reloaded).unwrap();
+        let (decoded, _) = decode_binary(&encode_binary(&original, None, true).unwrap()).unwrap();
+        verify_round_trip(&original, &decoded).unwrap();
+        let mixed = json.replacen("\"gray\"", "\"r\": 1.0, \"gray\"", 1);
+        assert!(serde_json::from_str::<Individual>(&mixed).is_err());
+    }
+
+    #[test]
+    fn fitness_changes_little() {
+        let rgba = (0..40 * 30).flat_map(|k| [(k % 30 * 8) as u8, (k / 30 * 6) as u8, 128, 255]).collect();
+        let target = Canvas::from_rgba(40, 30, rgba).unwrap();
+        let mut original = sample_individual();
+        let (mut decoded, _) = decode_binary(&encode_binary(&original, None, true).unwrap()).unwrap();
+        original.calc_fitness(&target);
+        decoded.calc_fitness(&target);
+        let change = (decoded.get_fitness() - original.get_fitness()).abs() / original.get_fitness();
+        assert!(change < 0.01, "适应度的相对变化 {} 过大", change);
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let checkpoint = Checkpoint { gen: 123, seed: Some(7), islands: vec![vec![sample_individual(); 2], vec![]] };
+        let decoded = decode_checkpoint(&encode_checkpoint(&checkpoint, true).unwrap()).unwrap();
+        assert_eq!((decoded.gen, decoded.seed), (123, Some(7)));
+        assert_eq!(decoded.islands.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 0]);
+        verify_round_trip(&checkpoint.islands[0][1], &decoded.islands[0][1]).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let ind = sample_individual();
+        for compress in [false, true] {
+            let bytes = encode_binary(&ind, None, compress).unwrap();
+            assert!(decode_binary(&bytes[..bytes.len() / 2]).is_err());
+            assert!(decode_binary(&bytes[..BINARY_MAGIC.len() + 1]).is_err());
+            let mut bad_magic = bytes.clone();
+            bad_magic[0] = b'X';
+            assert!(decode_binary(&bad_magic).is_err());
+        }
+        // 第一个图形的类别位于文件头 (6 字节), 尺寸 (8), 背景 (4), 量化区间 (24) 和图形数目 (4) 之后
+        let mut bytes = encode_binary(&ind, None, false).unwrap();
+        bytes[6 + 8 + 4 + 24 + 4] = 9;
+        assert_eq!(decode_binary(&bytes).err().as_deref(), Some("未知的图形类别"));
+    }
+}
@@ -0,0 +1,152 @@
+use image::RgbaImage;
+use serde::*;
+use crate::util::{Canvas, Individual, ShapeSummary};
+
+
+/// inspect 子命令的设置
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InspectOptions {
+    pub target: Option<String>,     // 目标图片, 给出时计算每个图形对适应度的贡献 (按个体元数据中原运行的设置裁剪和比对)
+    pub top: usize,                 // 列出贡献最大和最小的各多少个图形
+    pub heatmap: bool,              // 是否保存覆盖次数的热力图 (个体文件同目录下的 *_coverage.png)
+    pub json: bool,                 // 以 JSON 输出报告, 而不是文字
+}
+impl Default for InspectOptions {
+    fn default() -> InspectOptions {
+        InspectOptions {
+            target: None,
+            top: 10,
+            heatmap: true,
+            json: false,
+        }
+    }
+}
+
+
+/// 某一类图形的统计
+#[derive(Clone, Debug, Serialize)]
+pub struct KindStats {
+    pub kind: &'static str,
+    pub count: usize,
+    pub mean_area: f32,     // 平均几何面积 (像素²)
+    pub mean_alpha: f32,    // 平均不透明度
+}
+
+
+/// 一个个体的检查报告
+#[derive(Clone, Debug, Serialize)]
+pub struct Inspection {
+    pub size: (usize, usize),           // 画布尺寸 (高, 宽)
+    pub n_shapes: usize,
+    pub kinds: Vec<KindStats>,          // 各类图形的统计, 最后一项为全部图形 ("all")
+    pub uncovered_ratio: f32,           // 没有被任何图形覆盖的像素比例
+    pub mean_layers: f32,               // 每个像素平均被多少个图形覆盖
+    pub max_layers: u32,                // 覆盖次数的最大值
+    pub defects: Vec<(usize, Vec<&'static str>)>,   // 退化的图形: (z 序下标, 退化之处)
+    pub fitness: Option<f32>,           // 对 target 的适应度
+    pub contributions: Option<Vec<(usize, f32)>>,   // 每个图形移除后适应度的增量 (正数表示这个图形有用), 按增量从大到小排列
+}
+
+
+/// 检查个体. 给出 target (须与个体尺寸相同) 时逐个移除图形, 计算其对适应度的贡献
+pub fn inspect(ind: &Individual, target: Option<&Canvas>) -> Inspection {
+    let summaries = ind.shape_summaries();
+    let kind_stats = |kind: &'static str, shapes: Vec<&ShapeSummary>| {
+        let count = shapes.len();
+        let mean = |f: fn(&ShapeSummary) -> f32| if count == 0 { 0.0 } else { shapes.iter().map(|s| f(s)).sum::<f32>() / count as f32 };
+        KindStats { kind, count, mean_area: mean(|s| s.area), mean_alpha: mean(|s| s.alpha) }
+    };
+    let mut kinds: Vec<KindStats> = ["triangle", "circle", "rectangle"].into_iter()
+        .map(|kind| kind_stats(kind, summaries.iter().filter(|s| s.kind == kind).collect()))
+        .collect();
+    kinds.push(kind_stats("all", summaries.iter().collect()));
+
+    let coverage = ind.coverage();
+    let n_pixels = coverage.len().max(1) as f32;
+
+    let defects = summaries.into_iter().enumerate()
+        .filter(|(_, s)| !s.defects.is_empty())
+        .map(|(which, s)| (which, s.defects))
+        .collect();
+
+    let (fitness, contributions) = match target {
+        Some(target) => {
+            assert_eq!((target.x_height, target.y_width), ind.env_size(), "目标图片与个体的尺寸不同!");
+            let mut base = ind.clone();
+            base.calc_fitness(target);
+            let mut contributions: Vec<(usize, f32)> = (0..ind.n_shapes()).map(|which| {
+                let mut trial = ind.clone();
+                trial.remove_shape(which);
+                trial.calc_fitness(target);
+                (which, trial.get_fitness() - base.get_fitness())
+            }).collect();
+            contributions.sort_by(|a, b| b.1.total_cmp(&a.1));
+            (Some(base.get_fitness()), Some(contributions))
+        }
+        None => (None, None),
+    };
+
+    Inspection {
+        size: ind.env_size(),
+        n_shapes: ind.n_shapes(),
+        kinds,
+        uncovered_ratio: coverage.iter().filter(|&&count| count == 0).count() as f32 / n_pixels,
+        mean_layers: coverage.iter().sum::<u32>() as f32 / n_pixels,
+        max_layers: coverage.iter().copied().max().unwrap_or(0),
+        defects,
+        fitness,
+        contributions,
+    }
+}
+
+
+impl Inspection {
+    /// 以文字输出报告, 贡献只列出最大和最小的各 top 个
+    pub fn print(&self, top: usize) {
+        println!("尺寸 {}×{}, 共 {} 个图形", self.size.0, self.size.1, self.n_shapes);
+        println!("{:<10} {:>6} {:>12} {:>10}", "kind", "count", "mean_area", "mean_alpha");
+        for stats in &self.kinds {
+            println!("{:<10} {:>6} {:>12.1} {:>10.3}", stats.kind, stats.count, stats.mean_area, stats.mean_alpha);
+        }
+        println!("覆盖: {:.1}% 的像素没有被覆盖, 平均 {:.2} 层, 最多 {} 层", self.uncovered_ratio * 100.0, self.mean_layers, self.max_layers);
+
+        if self.defects.is_empty() {
+            println!("没有退化的图形");
+        } else {
+            println!("{} 个退化的图形:", self.defects.len());
+            for (which, defects) in &self.defects {
+                println!("  #{}: {}", which, defects.join(", "));
+            }
+        }
+
+        if let (Some(fitness), Some(contributions)) = (self.fitness, &self.contributions) {
+            println!("fitness = {}", fitness);
+            let useless = contributions.iter().filter(|(_, delta)| *delta <= 0.0).count();
+            println!("{} 个图形移除后适应度不变差 (可用 prune 移除)", useless);
+            let n = top.min(contributions.len());
+            println!("贡献最大的 {} 个图形 (移除后适应度的增量):", n);
+            for (which, delta) in &contributions[..n] {
+                println!("  #{}: {:+.5}", which, delta);
+            }
+            println!("贡献最小的 {} 个图形:", n);
+            for (which, delta) in contributions[contributions.len() - n..].iter().rev() {
+                println!("  #{}: {:+.5}", which, delta);
+            }
+        }
+    }
+}
+
+
+/// 覆盖次数的热力图: 没有覆盖为黑色, 依次经红、黄到白色为最多的覆盖次数
+pub fn coverage_heatmap(ind: &Individual) -> RgbaImage {
+    let (x_height, y_width) = ind.env_size();
+    let coverage = ind.coverage();
+    let max_layers = coverage.iter().copied().max().unwrap_or(0).max(1) as f32;
+    RgbaImage::from_fn(y_width as u32, x_height as u32, |j, i| {
+        let t = coverage[i as usize * y_width + j as usize] as f32 / max_layers;
+        let channel = |offset: f32| ((3.0 * t - offset).clamp(0.0, 1.0) * 255.0).round() as u8;
+        image::Rgba([channel(0.0), channel(1.0), channel(2.0), 255])
+    })
+}
@@ -0,0 +1,148 @@
+use std::thread;
+use serde::*;
+use crate::config::{Config, Topology};
+use crate::evolve::*;
+use crate::util::*;
+
+
+/// 可以从中恢复运行的快照: 已完成的轮数、随机数种子和全部岛屿的全部个体 (演化分辨率下)
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub gen: usize,
+    #[serde(default)]
+    pub seed: Option<u64>,          // 运行的随机数种子, 恢复时沿用 (较早的检查点没有)
+    pub islands: Vec<Vec<Individual>>,
+}
+
+
+/// 岛屿模型: 若干个子种群各自独立演化, 每隔 migration_interval 轮按照拓扑结构交换最优个体
+pub struct Archipelago {
+    pub islands: Vec<Population>,
+    n_gen: usize,
+}
+impl Archipelago {
+    /// 创建 n_islands 个随机初始化的岛屿
+    pub fn new(target: &Canvas, cfg: &Config) -> Archipelago {
+        Archipelago {
+            islands: (0..cfg.n_islands).map(|_| Population::new(target, cfg)).collect(),
+            n_gen: 0,
+        }
+    }
+
+    /// 从检查点恢复. 岛屿数目以检查点为准, 个体缩放到目标图片的尺寸上
+    pub fn from_checkpoint(checkpoint: Checkpoint, target: &Canvas, cfg: &Config) -> Archipelago {
+        Archipelago {
+            islands: checkpoint.islands.into_iter().map(|individuals| Population::from_individuals(individuals, target, cfg)).collect(),
+            n_gen: checkpoint.gen,
+        }
+    }
+
+    /// 第 gen 轮结束时的检查点
+    pub fn checkpoint(&self, gen: usize, seed: Option<u64>) -> Checkpoint {
+        Checkpoint {
+            gen,
+            seed,
+            islands: self.islands.iter().map(|island| island.individuals.clone()).collect(),
+        }
+    }
+
+    /// 全部岛屿中最优的个体
+    pub fn best(&self) -> &Individual {
+        self.islands.iter()
+            .map(|island| island.best())
+            .min_by(|ind1, ind2| ind1.get_fitness().partial_cmp(&ind2.get_fitness()).unwrap())
+            .unwrap()
+    }
+
+    /// 全部岛屿的全部个体
+    pub fn individuals(&self) -> impl Iterator<Item = &Individual> {
+        self.islands.iter().flat_map(|island| island.individuals.iter())
+    }
+
+    /// 全部岛屿最近一轮的统计数据之和
+    pub fn last_stats(&self) -> StepStats {
+        let mut stats = StepStats::default();
+        for island in &self.islands {
+            stats.add(&island.last_stats);
+        }
+        stats
+    }
+
+    /// 全部岛屿各演化一轮 (图形颜色受 colors 约束), 到了迁移的时间则进行迁移
+    pub fn step(&mut self, target: &Canvas, cfg: &Config, colors: &ColorModel) {
+        if cfg.parallel && self.islands.len() > 1 {
+            thread::scope(|scope| {
+                for island in &mut self.islands {
+                    let seed = random::derive_seed();   // 由当前线程派生, 使设定了 seed 的运行在多线程下也能复现
+                    scope.spawn(move || {
+                        random::seed(seed);
+                        island.step(target, cfg, colors)
+                    });
+                }
+            });
+        } else {
+            for island in &mut self.islands {
+                island.step(target, cfg, colors);
+            }
+        }
+        self.n_gen += 1;
+        if self.islands.len() > 1 && self.n_gen.is_multiple_of(cfg.migration_interval) {
+            self.migrate(cfg);
+        }
+    }
+
+    /// 将全部岛屿缩放到新的目标图片的尺寸上
+    pub fn rescale(&mut self, target: &Canvas) {
+        for island in &mut self.islands {
+            island.rescale(target);
+        }
+    }
+
+    /// 为全部岛屿设置时间连贯性的参照个体和惩罚权重 (见 Population::set_anchor)
+    pub fn set_anchor(&mut self, anchor: Option<(Individual, f32)>) {
+        for island in &mut self.islands {
+            island.set_anchor(anchor.clone());
+        }
+    }
+
+    /// 修剪全部岛屿中最优的个体多余的图形, 返回移除的图形数目
+    pub fn prune_best(&mut self, target: &Canvas) -> usize {
+        self.islands.iter_mut()
+            .min_by(|island1, island2| island1.best().get_fitness().total_cmp(&island2.best().get_fitness()))
+            .unwrap()
+            .prune_best(target)
+    }
+
+    /// 全部个体两两之间基因差异的平均值, 衡量种群的多样性
+    pub fn diversity(&self) -> f32 {
+        let individuals: Vec<&Individual> = self.individuals().collect();
+        let n = individuals.len();
+        if n < 2 { return 0.0 }
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in i + 1..n {
+                sum += individuals[i].distance(individuals[j]);
+            }
+        }
+        sum / (n * (n - 1) / 2) as f32
+    }
+
+    /// 按照拓扑结构, 把每个岛屿最优的 n_migrants 个个体复制到目标岛屿, 与那里的个体一起按适应度保留 (见 Population::receive)
+    fn migrate(&mut self, cfg: &Config) {
+        let n = self.islands.len();
+        // 先拍下全部岛屿的快照, 避免本轮刚迁入的个体被继续转送
+        let emigrants: Vec<Vec<Individual>> = self.islands.iter()
+            .map(|island| island.individuals.iter().take(cfg.n_migrants).cloned().collect())
+            .collect();
+        for (i, island) in self.islands.iter_mut().enumerate() {
+            let sources: Vec<usize> = match cfg.topology {
+                Topology::Ring => vec![(i + n - 1) % n],
+                Topology::FullyConnected => (0..n).filter(|&j| j != i).collect(),
+            };
+            let migrants = sources.iter()
+                .flat_map(|&j| emigrants[j].iter().cloned())
+                .collect();
+            island.receive(migrants);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! EARtist: 用若干半透明的三角形、圆形、矩形, 通过演化算法逼近目标图片.
+//! 核心 (Canvas, Individual, 演化) 不依赖文件系统以外的平台特性, 可以编译到 wasm32-unknown-unknown;
+//! 终端仪表盘和 HTTP 服务只在原生平台上编译, wasm 绑定只在 wasm32 上编译
+
+pub mod util;
+pub mod genome;
+pub mod cmaes;
+pub mod config;
+pub mod evolve;
+pub mod island;
+pub mod nsga;
+pub mod palette;
+pub mod anim;
+pub mod metrics;
+pub mod batch;
+pub mod inspect;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tui;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
@@ -1,122 +1,607 @@
-mod util;
-
-use std::cmp::min;
-use util::*;
+use std::env;
+use eartist::{anim, batch, cmaes, config, genome, inspect, metrics, server, tui};
+use eartist::genome::GenomeMetadata;
+use eartist::util::*;
+use eartist::config::{ColorMode, Config};
+use eartist::island::{Archipelago, Checkpoint};
+use eartist::nsga::ParetoPopulation;
 use std::fs;
-use chrono;
+use std::time::Instant;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /* TODO list:
-    - 编译为 Wasm, 在 Web 端实现可调的超参数交互 (main 中的 const 变量绝大多数应实现交互可改)
+    - 基于 wasm 绑定 (src/wasm.rs) 的 Web 前端, 实现可调的超参数交互
     - 允许 [定期/交互性] 移除个体中 [面积过小] 的图形
-    - 允许 [定期/交互性] 扫描个体的全部图形, 尝试移除 (比如如果移除后 fitness 上升, 则移除之)
 */
 
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(|s| s.as_str()) {
+        Some("polish") => polish(&args[2..]),
+        Some("pareto") => pareto(&args[2..]),
+        Some("timelapse") => timelapse(&args[2..]),
+        Some("buildup") => buildup(&args[2..]),
+        Some("batch") => batch(&args[2..]),
+        Some("video") => video(&args[2..]),
+        Some("inspect") => inspect(&args[2..]),
+        Some("convert") => convert(&args[2..]),
+        _ => evolve(&args[1..]),
+    }
+}
 
-    // 读取目标图片
-    let target = Canvas::read_from_file("./src/data/target.jpg");
-    let x_height = target.x_height;
-    let y_width = target.y_width;
-    let canvas_size = min(x_height, y_width);
 
-    // 保存文件夹
+/// 用法: eartist polish <genome.json> [target] [n_iters]
+/// 读取已保存的个体, 用 CMA-ES 精修其连续参数, 结果保存为同目录下的 *_polished.json 和 *_polished.png.
+/// 没有给出 target 时使用原运行的目标图片
+fn polish(args: &[String]) {
+    const POLISH_SIGMA: f32 = 0.01;     // 初始步长 (坐标以画布短边为单位, 颜色以 255 为单位)
+    const POLISH_ITERS: usize = 1000;   // 默认迭代代数
+
+    let genome_path = args.first().expect("用法: eartist polish <genome.json> [target] [n_iters]");
+    let target_path = args.get(1).map(|s| s.as_str());
+    let n_iters = args.get(2).map(|s| s.parse().ok().filter(|&n| n >= 1).expect("n_iters 必须是正整数")).unwrap_or(POLISH_ITERS);
+
+    let (ind, metadata) = genome::read_file(genome_path);
+    let run_config = run_config(metadata.as_ref());
+    let target = target_for(&ind, run_config.as_ref(), target_path);     // 在个体自身的尺寸上精修, 按原运行的设置裁剪和比对
+
+    // 颜色沿用原运行的颜色约束. 从目标提取的调色板 (k-means) 无法复现, 改用个体已有的颜色
+    let colors = match &run_config {
+        Some(cfg) if cfg.color_mode == ColorMode::Rgb && cfg.palette.is_empty() && cfg.palette_image.is_none() && cfg.palette_from_target =>
+            ColorModel::Palette(ind.distinct_colors()),
+        Some(cfg) => cfg.color_model(&target),
+        None => ColorModel::Free,
+    };
+
+    let polished = cmaes::polish(&ind, &target, &colors, POLISH_SIGMA, n_iters, |iter, best, sigma| {
+        println!("第 {} 轮精修: best fitness = {}, sigma = {}", iter, best.get_fitness(), sigma);
+    });
+
+    let stem = std::path::Path::new(genome_path).with_extension("").to_string_lossy().into_owned();
+    let json = match &metadata {
+        Some(metadata) => polished.to_json_with_metadata(metadata),     // 沿用原个体的元数据
+        None => polished.to_json(),
+    };
+    fs::write(format!("{}_polished.json", stem), json).unwrap();
+    let grayscale = run_config.is_some_and(|cfg| cfg.color_mode == ColorMode::Gray);     // 按原运行的颜色模式输出
+    polished.draw_self().write_to_file(format!("{}_polished.png", stem).as_str(), grayscale);
+}
+
+
+/// 用法: eartist timelapse <folder> [--key value ...]
+/// 将一次演化保存下来的各代图片 ({gen}.png) 合成为 GIF/APNG 延时动画, 可用 --format, --fps, --every, --max_frames, --max_dim, --output 调整
+fn timelapse(args: &[String]) {
+    let folder = args.first().expect("用法: eartist timelapse <folder> [--key value ...]");
+    let opts: anim::TimelapseOptions = config::parse_args(&args[1..]);
+    opts.validate();
+    let output = anim::timelapse(folder, &opts);
+    println!("延时动画已保存到 {}", output);
+}
+
+
+/// 用法: eartist buildup <genome.json> [--key value ...]
+/// 将已保存的个体导出为逐个图形 (按 z 序) 出现的 GIF/APNG/SVG 动画,
+/// 可用 --format, --fps, --shapes_per_frame, --fade_frames, --hold_frames, --max_dim, --output 调整
+fn buildup(args: &[String]) {
+    let genome_path = args.first().expect("用法: eartist buildup <genome.json> [--key value ...]");
+    let opts: anim::BuildUpOptions = config::parse_args(&args[1..]);
+    opts.validate();
+    let (ind, _) = genome::read_file(genome_path);
+    let output = anim::build_up(&ind, genome_path, &opts);
+    println!("叠加动画已保存到 {}", output);
+}
+
+
+/// 用法: eartist inspect <genome.json> [--key value ...]
+/// 报告已保存个体的图形统计、覆盖情况和退化的图形. 给出 --target 时还报告每个图形对适应度的贡献 (移除后的增量),
+/// 覆盖次数的热力图保存为同目录下的 *_coverage.png. 可用 --top, --heatmap, --json 调整
+fn inspect(args: &[String]) {
+    let genome_path = args.first().expect("用法: eartist inspect <genome.json> [--key value ...]");
+    let opts: inspect::InspectOptions = config::parse_args(&args[1..]);
+    let (ind, metadata) = genome::read_file(genome_path);
+    let target = opts.target.as_deref().map(|path| target_for(&ind, run_config(metadata.as_ref()).as_ref(), Some(path)));
+
+    let report = inspect::inspect(&ind, target.as_ref());
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        report.print(opts.top);
+    }
+    if opts.heatmap {
+        let output = format!("{}_coverage.png", std::path::Path::new(genome_path).with_extension("").display());
+        inspect::coverage_heatmap(&ind).save(&output).unwrap();
+        if !opts.json {
+            println!("覆盖热力图已保存到 {}", output);
+        }
+    }
+}
+
+
+/// 用法: eartist convert <input> <output> [--key value ...]
+/// 在 JSON (任意版本) 和紧凑的二进制格式之间转换基因文件, 按输出文件的扩展名决定格式 (.json 为 JSON, 其余为二进制).
+/// 转为二进制时解码检查: 结构不变、每个参数的差异在量化误差内, 给出 --target 时适应度的相对变化不超过 --tolerance
+fn convert(args: &[String]) {
+    let usage = "用法: eartist convert <input> <output> [--key value ...]";
+    let (input, output) = (args.first().expect(usage), args.get(1).expect(usage));
+    let opts: genome::ConvertOptions = config::parse_args(&args[2..]);
+    let (ind, metadata) = genome::read_file(input);
+
+    let bytes = if output.ends_with(".json") {
+        match &metadata {
+            Some(metadata) => ind.to_json_with_metadata(metadata),
+            None => ind.to_json(),
+        }.into_bytes()
+    } else {
+        let bytes = genome::encode_binary(&ind, metadata.as_ref(), opts.compress).unwrap_or_else(|message| panic!("{}", message));
+        let (decoded, decoded_metadata) = genome::decode_binary(&bytes).unwrap_or_else(|message| panic!("{}", message));
+        genome::verify_round_trip(&ind, &decoded).unwrap_or_else(|message| panic!("往返检查失败: {}", message));
+        assert!(decoded_metadata == metadata, "往返检查失败: 元数据不同");
+        if let Some(target_path) = &opts.target {
+            let target = target_for(&ind, run_config(metadata.as_ref()).as_ref(), Some(target_path));
+            let (mut before, mut after) = (ind.clone(), decoded);
+            before.calc_fitness(&target);
+            after.calc_fitness(&target);
+            let change = (after.get_fitness() - before.get_fitness()).abs() / before.get_fitness().max(f32::EPSILON);
+            println!("fitness: {} → {} (相对变化 {:.5})", before.get_fitness(), after.get_fitness(), change);
+            assert!(change <= opts.tolerance, "往返检查失败: 适应度的相对变化 {} 超过了 tolerance {}", change, opts.tolerance);
+        }
+        bytes
+    };
+    fs::write(output, &bytes).unwrap();
+    println!("{} ({} 字节) → {} ({} 字节)", input, fs::metadata(input).unwrap().len(), output, bytes.len());
+}
+
+
+/// 用法: eartist pareto [--config config.json] [--key value ...]
+/// NSGA-II 多目标演化, 同时优化适应度和图形数目.
+/// Pareto 前沿上每种图形数目的最优个体 (按原尺寸) 保存到 {output_dir}/pareto_{datetime}/{n_shapes}.json 和 .png
+fn pareto(args: &[String]) {
+    const PARETO_PR_REMOVE_SHAPE: f32 = 0.1;    // 未设置 pr_remove_shape 时的取值, 使前沿能向图形较少的一端延伸
+
+    let mut cfg = Config::from_args(args);
+    cfg.pr_remove_shape.get_or_insert(PARETO_PR_REMOVE_SHAPE);
+    cfg.seed_rng();
+    let (target, (original_height, original_width)) = cfg.load_target();
+    let target_hash = hash_file(&cfg.target);
+
+    let datetime = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let save_folder = format!("{}/pareto_{}", cfg.output_dir, datetime);
+
+    let colors = cfg.color_model(&target);
+    let mut population = ParetoPopulation::new(&target, &cfg);
+    for gen in 1..=cfg.pareto_generations {
+        population.step(&target, &cfg, &colors);
+        let front = population.front();
+        println!("第 {} 轮: Pareto 前沿上有 {} 个个体, 图形数目 {}..={}", gen, front.len(),
+                 front.first().unwrap().n_shapes(), front.last().unwrap().n_shapes());
+
+        if gen % cfg.pareto_export_interval == 0 || gen == cfg.pareto_generations {
+            // 重新导出前先清空, 避免残留已不在前沿上的个体
+            if std::path::Path::new(&save_folder).exists() {
+                fs::remove_dir_all(&save_folder).unwrap();
+            }
+            fs::create_dir_all(&save_folder).unwrap();
+            let metadata = genome_metadata(&cfg, gen, &target_hash);
+            for ind in front {
+                let ind = ind.rescaled(original_height, original_width);
+                fs::write(format!("{}/{}.json", save_folder, ind.n_shapes()), ind.to_json_with_metadata(&metadata)).unwrap();
+                ind.draw_self().write_to_file(format!("{}/{}.png", save_folder, ind.n_shapes()).as_str(), cfg.color_mode == ColorMode::Gray);
+                if cfg.save_svg {
+                    fs::write(format!("{}/{}.svg", save_folder, ind.n_shapes()), ind.to_svg()).unwrap();
+                }
+            }
+        }
+    }
+}
+
+
+/// 用法: eartist [--config config.json] [--key value ...]
+/// 按照配置演化出逼近目标图片的个体, 过程中的最优个体按 save_schedule 保存到 {output_dir}/generation_best_{datetime}/.
+/// 满足停止条件或收到 Ctrl-C 时, 保存最终结果 final.png, final.json 和检查点 checkpoint.json (save_binary 时为 checkpoint.eag) 后退出
+fn evolve(args: &[String]) {
+    let cfg = Config::from_args(args);
+    install_interrupt_handler();
+    let datetime = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let save_folder = format!("{}/generation_best_{}", cfg.output_dir, datetime);
+    let summary = run(cfg, &save_folder, true);
+    println!("第 {} 轮停止: {}. 最终结果和检查点已保存到 {}", summary.generations, summary.stop_reason, save_folder);
+}
+
+
+/// 用法: eartist batch <folder 或 通配模式> [--config config.json] [--key value ...]
+/// 用同一份配置依次 (最多 jobs 个同时) 演化每一张图片, 必须给出停止条件.
+/// 每张图片的结果保存在 {output_dir}/batch_{datetime}/{文件名}/ 中, 汇总报告为同一文件夹中的 summary.csv
+fn batch(args: &[String]) {
+    let spec = args.first().expect("用法: eartist batch <folder 或 通配模式> [--key value ...]");
+    let cfg = Config::from_args(&args[1..]);
+    assert!(cfg.has_stop_criteria(), "batch 模式需要至少一个停止条件 (max_generations, max_seconds, target_fitness, max_shapes, patience)!");
+    assert!(!cfg.tui && cfg.serve.is_none() && cfg.resume.is_none(), "batch 模式不支持 tui, serve 和 resume!");
+    let targets = batch::list_targets(spec);
+    assert!(!targets.is_empty(), "{} 中没有图片!", spec);
+    install_interrupt_handler();
+
+    let datetime = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let batch_folder = format!("{}/batch_{}", cfg.output_dir, datetime);
+    fs::create_dir_all(&batch_folder).unwrap();
+    println!("共 {} 张图片, 同时处理 {} 张", targets.len(), cfg.jobs);
+
+    // 各线程从同一个队列中领取下一张图片, 直到全部处理完 (或收到 Ctrl-C)
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, batch::BatchResult)>> = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..cfg.jobs.min(targets.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= targets.len() || INTERRUPTED.load(Ordering::SeqCst) { break }
+                let target = targets[i].to_string_lossy().into_owned();
+                let name = targets[i].file_name().unwrap().to_string_lossy().into_owned();
+                let cfg = Config { target: target.clone(), ..cfg.clone() };
+                // 一张图片失败 (如无法读取) 时记录下原因, 继续处理其余的图片
+                let outcome = std::panic::catch_unwind(|| run(cfg, &format!("{}/{}", batch_folder, name), false))
+                    .map(|summary| batch::BatchStats {
+                        generations: summary.generations,
+                        seconds: summary.seconds,
+                        fitness: summary.fitness,
+                        n_shapes: summary.n_shapes,
+                        stop_reason: summary.stop_reason,
+                    })
+                    .map_err(|payload| panic_message(&*payload));
+                match &outcome {
+                    Ok(s) => println!("[{}/{}] {}: fitness = {}, n_shapes = {}, 第 {} 轮停止 ({})",
+                                      i + 1, targets.len(), target, s.fitness, s.n_shapes, s.generations, s.stop_reason),
+                    Err(error) => println!("[{}/{}] {}: 失败: {}", i + 1, targets.len(), target, error),
+                }
+                results.lock().unwrap().push((i, batch::BatchResult { target, outcome }));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    let results: Vec<batch::BatchResult> = results.into_iter().map(|(_, result)| result).collect();
+    let report = format!("{}/summary.csv", batch_folder);
+    batch::write_report(&report, &results);
+    let n_failed = results.iter().filter(|r| r.outcome.is_err()).count();
+    println!("完成 {} 张图片 ({} 张失败), 汇总报告已保存到 {}", results.len(), n_failed, report);
+}
+
+
+/// panic 携带的信息
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "未知错误".to_string())
+}
+
+
+/// 用法: eartist video <frames_folder> [--config config.json] [--key value ...]
+/// 逐帧演化编号的 PNG 帧序列: 第一帧从随机种群演化 video_first_generations 轮, 之后每帧从上一帧的种群出发演化 video_generations 轮,
+/// 并以 temporal_weight 惩罚与上一帧最优个体的差异, 避免画面闪烁. 不使用 pyramid 日程.
+/// 每帧的结果 (按原尺寸) 保存为 {output_dir}/video_{datetime}/{帧文件名}.png 和 .json
+fn video(args: &[String]) {
+    let folder = args.first().expect("用法: eartist video <frames_folder> [--key value ...]");
+    let mut cfg = Config::from_args(&args[1..]);
+    cfg.seed_rng();
+    let frames = anim::numbered_frames(folder);
+    assert!(!frames.is_empty(), "文件夹 {} 中没有带编号的 PNG 帧!", folder);
+    install_interrupt_handler();
+
     let datetime = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
-    let save_folder = format!("./src/result/generation_best_{}", datetime);
-    if !std::path::Path::new(&save_folder).exists() {
-        fs::create_dir_all(&save_folder).unwrap();
+    let save_folder = format!("{}/video_{}", cfg.output_dir, datetime);
+    fs::create_dir_all(&save_folder).unwrap();
+
+    let mut archipelago: Option<Archipelago> = None;
+    let mut colors: Option<ColorModel> = None;
+    let mut total_gens = 0;     // 至今各帧演化的总轮数
+    for (i, frame) in frames.iter().enumerate() {
+        if INTERRUPTED.load(Ordering::SeqCst) { break }
+        let frame_cfg = Config { target: frame.to_string_lossy().into_owned(), ..cfg.clone() };
+        let (target, (original_height, original_width)) = frame_cfg.load_target();
+        let colors = colors.get_or_insert_with(|| cfg.color_model(&target));    // 调色板由第一帧决定, 保持各帧一致
+
+        // 第一帧从随机种群开始; 之后的帧沿用上一帧的种群, 以上一帧的最优个体为参照重新计算适应度
+        let n_gens = match &mut archipelago {
+            None => {
+                archipelago = Some(Archipelago::new(&target, &cfg));
+                cfg.video_first_generations
+            }
+            Some(archipelago) => {
+                let previous_best = archipelago.best().clone();
+                archipelago.set_anchor(Some((previous_best, cfg.temporal_weight)));
+                archipelago.rescale(&target);
+                cfg.video_generations
+            }
+        };
+        let archipelago = archipelago.as_mut().unwrap();
+        for _ in 0..n_gens {
+            archipelago.step(&target, &cfg, colors);
+        }
+        total_gens += n_gens;
+
+        let best = archipelago.best();
+        let stem = format!("{}/{}", save_folder, frame.file_stem().unwrap().to_string_lossy());
+        let best_rescaled = best.rescaled(original_height, original_width);
+        best_rescaled.draw_self().write_to_file(format!("{}.png", stem).as_str(), cfg.color_mode == ColorMode::Gray);
+        let metadata = genome_metadata(&frame_cfg, total_gens, &hash_file(&frame_cfg.target));
+        fs::write(format!("{}.json", stem), best_rescaled.to_json_with_metadata(&metadata)).unwrap();
+        let mut unpenalized = best.rescaled(target.x_height, target.y_width);     // 不含时间连贯性惩罚的适应度
+        unpenalized.calc_fitness(&target);
+        println!("[{}/{}] {}: fitness = {}, 时间连贯性惩罚 = {}, n_shapes = {}", i + 1, frames.len(), frame.display(),
+                 unpenalized.get_fitness(), best.get_fitness() - unpenalized.get_fitness(), best.n_shapes());
     }
+    println!("帧序列已保存到 {}", save_folder);
+}
 
-    // 设定种群超参数
-    const POP_SIZE: usize = 4;          // 种群大小. 取值范围 [1, ∞)
-    const PROP_AMOUNT: usize = 4;       // 每个个体的产仔数. 取值范围 [1, ∞)
-    const N_GUARD: usize = 2;           // 上一轮的前 N_GUARD 个个体也参与本轮竞争 (而非产仔后立刻抛弃). 取值范围 [0, POP_SIZE]
 
-    const BG_COLOR: (f32, f32, f32) = (0., 0., 0.);  // 背景色
+/// Ctrl-C 时不立即退出, 而是让各次运行在这一轮结束后保存并停止 (开启仪表盘时 Ctrl-C 作为按键处理)
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+fn install_interrupt_handler() {
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)).expect("无法设置 Ctrl-C 处理函数");
+}
+
+
+/// 一次运行的结果
+struct RunSummary {
+    generations: usize,     // 停止时的轮数
+    seconds: f32,           // 演化所用的秒数
+    fitness: f32,           // 最终的最优适应度
+    n_shapes: usize,        // 最终最优个体的图形数目
+    stop_reason: String,
+}
 
-    const MUTATE_RATIO: f32 = 0.1;      // 最多多少比例的图形发生变异
-    const MUTATE_AMP: f32 = 1.0;        // 变异剧烈程度
 
-    const PR_ADD_SHAPE: f32 = 0.5;      // 每个新个体尝试新增一个图形的概率
-    const PR_TRIANGLE: f32 = 3.14;       // 使用三角形的概率权重
-    const PR_CIRCLE: f32 = 1.23;         // 使用圆形的概率权重
-    const PR_RECTANGLE: f32 = 1.35;      // 使用长方形的概率权重
-    // 在生成时, 首先按照 PR_ADD_SHAPE 决定 ｢是否生成｣. 如果 ｢是｣, 再根据三个图形的概率权重抽取其中一个进行生成.
-    // 这里后三个变量在交互上可以实现为 ｢等边三角图｣
-    assert!(PR_TRIANGLE + PR_CIRCLE + PR_RECTANGLE > 0.0, "这三个不能全为 0!");
-    assert!(PR_TRIANGLE >= 0.0 && PR_CIRCLE >= 0.0 && PR_RECTANGLE >= 0.0, "概率权重不能为负数!");
+/// 按照 cfg 演化一次, 结果保存到 save_folder. verbose 为 false 时不逐轮打印
+fn run(mut cfg: Config, save_folder: &str, verbose: bool) -> RunSummary {
+    let verbose = verbose && !cfg.tui;      // 开启仪表盘时代替逐轮打印
 
+    // 读取并预处理目标图片, 再按 pyramid 日程缩放到起始分辨率
+    let (full_target, (original_height, original_width)) = cfg.load_target();
+    let target_hash = hash_file(&cfg.target);
+    let (full_height, full_width) = (full_target.x_height, full_target.y_width);
+    let level_size = |scale: f32| {
+        (((full_height as f32 * scale).round() as usize).max(1), ((full_width as f32 * scale).round() as usize).max(1))
+    };
+    let checkpoint: Option<Checkpoint> = cfg.resume.as_deref().map(genome::read_checkpoint);
+    let start_gen = checkpoint.as_ref().map_or(1, |checkpoint| checkpoint.gen + 1);
 
-    // 创建最初的随机种群
-    let mut last_population: Vec<Individual> = Vec::new();
-    for _ in 0..POP_SIZE {
-        last_population.push(Individual::new(x_height, y_width, BG_COLOR));
+    // 随机数种子: 恢复运行时沿用检查点中的种子 (除非另外指定). 须在 k-means 调色板等一切随机过程之前设定
+    if let Some(checkpoint) = &checkpoint {
+        cfg.seed = cfg.seed.or(checkpoint.seed);
     }
+    cfg.seed_rng();
+    let mut scale = cfg.scale_at(start_gen);
+    let (x_height, y_width) = level_size(scale);
+    let mut target = full_target.resized(x_height, y_width, cfg.resize_filter);
 
-    // 开始迭代
-    for gen in 1..=100_000_000 {
-        println!("第 {} 轮开始迭代", gen);
+    // 保存文件夹
+    if !std::path::Path::new(save_folder).exists() {
+        fs::create_dir_all(save_folder).unwrap();
+    }
+
+    // 图形颜色的取值约束 (调色板)
+    let colors = cfg.color_model(&full_target);
+
+    // 创建最初的随机种群 (n_islands 个岛屿), 或者从检查点恢复
+    let mut archipelago = match checkpoint {
+        Some(checkpoint) => Archipelago::from_checkpoint(checkpoint, &target, &cfg),
+        None => Archipelago::new(&target, &cfg),
+    };
+
+    // 指标日志
+    let mut metrics_log = cfg.metrics.map(|format| metrics::MetricsLog::create(save_folder, format));
+    let start = Instant::now();
+
+    // 本地 HTTP 服务 (实时预览, 运行中修改参数)
+    let server = cfg.serve.map(|port| server::Server::start(port, &cfg));
+
+    // 终端仪表盘 (开启时代替逐轮打印)
+    let mut dashboard = cfg.tui.then(tui::Dashboard::open);
 
-        let mut new_generation: Vec<Individual> = Vec::with_capacity(POP_SIZE * PROP_AMOUNT + N_GUARD);
+    // 开始迭代, 直到满足停止条件
+    let mut last_save: Option<(f32, Instant)> = None;     // 上次保存时的最优适应度和时刻
+    let (mut best_fitness, mut stagnant_gens) = (f32::INFINITY, 0);     // 历史最优适应度, 以及连续没有改进的轮数
+    let mut gen = start_gen;
+    let stop_reason = loop {
+        if verbose {
+            println!("第 {} 轮开始迭代", gen);
+        }
+
+        // 到达 pyramid 日程的下一级时, 将目标图片和全部个体放大到新的分辨率
+        if cfg.scale_at(gen) != scale {
+            scale = cfg.scale_at(gen);
+            let (x_height, y_width) = level_size(scale);
+            target = full_target.resized(x_height, y_width, cfg.resize_filter);
+            archipelago.rescale(&target);
+            // 更高的分辨率下适应度会变差, 停止条件和按改进保存的参照都从新的分辨率重新开始
+            (best_fitness, stagnant_gens) = (f32::INFINITY, 0);
+            last_save = last_save.map(|(_, time)| (f32::INFINITY, time));
+            match &mut dashboard {
+                Some(dashboard) => dashboard.notify(format!("第 {} 轮切换到 {}×{} 分辨率", gen, x_height, y_width)),
+                None if verbose => println!("切换到 {}×{} 分辨率", x_height, y_width),
+                None => {}
+            }
+        }
 
-        // 每个个体产生 PROP_AMOUNT 个变异幼崽
-        for ind in &last_population {
-            for _ in 0..PROP_AMOUNT {
-                let mut child = ind.clone();
-                // 对自己至多 MUTATE_RATIO 的图形进行突变
-                let mutate_amount = random::randint(0, (ind.n_shapes() as f32 * MUTATE_RATIO) as usize + 1);
-                for _ in 0..mutate_amount {
-                    child.mutate_shape(random::randint(0usize, ind.n_shapes()), canvas_size, MUTATE_AMP);
+        // 应用 HTTP 服务收到的参数修改
+        if let Some(server) = &server {
+            for (patch, reply) in server.pending_patches() {
+                let result = cfg.patched(patch).map(|new_cfg| {
+                    cfg = new_cfg;
+                    serde_json::to_string(&cfg).unwrap()
+                });
+                if let (Some(dashboard), Ok(_)) = (&mut dashboard, &result) {
+                    dashboard.notify(format!("第 {} 轮已通过 HTTP 修改参数", gen));
                 }
-                // 以 ADD_SHAPE_PR 的概率新增一个图形
-                if random::uniform(0., 1.) < PR_ADD_SHAPE {     // 决定是否新增一个图形
-                    let shape_chosen = random::weighted_choice(
-                        &["triangle", "circle", "rectangle"],           // 按照权重随机抽取一个图形
-                        &[PR_TRIANGLE, PR_CIRCLE, PR_RECTANGLE]);
-                    child.add_shape(shape_chosen);
+                let _ = reply.send(result);
+            }
+        }
+
+        let step_start = Instant::now();
+        archipelago.step(&target, &cfg, &colors);
+        if let Some(log) = &mut metrics_log {
+            let row = metrics::MetricsRow::new(gen, start.elapsed().as_secs_f64(), archipelago.individuals(),
+                                               &archipelago.last_stats(), step_start.elapsed().as_secs_f64());
+            log.write(&row);
+        }
+
+        if let Some(server) = &server {
+            server.publish(gen, archipelago.best(), &cfg);
+        }
+
+        // 处理仪表盘的按键 (暂停期间在此等待), 或者打印最优个体的适应度
+        let mut force_save = false;
+        if let Some(dashboard) = &mut dashboard {
+            dashboard.record(archipelago.best().get_fitness());
+            loop {
+                for command in dashboard.poll() {
+                    match command {
+                        tui::Command::Pause => dashboard.paused = !dashboard.paused,
+                        tui::Command::Save => force_save = true,
+                        tui::Command::Prune => {
+                            let n_removed = archipelago.prune_best(&target);
+                            dashboard.notify(format!("第 {} 轮从最优个体中修剪掉 {} 个图形", gen, n_removed));
+                        }
+                        tui::Command::Quit => INTERRUPTED.store(true, Ordering::SeqCst),     // 与 Ctrl-C 一样结束, 保存最终结果
+                    }
                 }
-                new_generation.push(child);
+                if dashboard.is_due() || dashboard.paused {
+                    let amps = cfg.adaptive_amp.then(|| archipelago.islands.iter().map(|island| island.amp(&cfg)).collect());
+                    dashboard.draw(&tui::Status {
+                        gen,
+                        best: archipelago.best(),
+                        resolution: (target.x_height, target.y_width),
+                        diversity: archipelago.diversity(),
+                        acceptance_rate: archipelago.last_stats().acceptance_rate(),
+                        amps,
+                    });
+                }
+                if !dashboard.paused || force_save || INTERRUPTED.load(Ordering::SeqCst) { break }
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+        } else if verbose {
+            let gen_best = archipelago.best();
+            println!("best fitness = {}", gen_best.get_fitness());
+            println!("his n_shapes = {}", gen_best.n_shapes());
+            if cfg.adaptive_amp {
+                let amps: Vec<f32> = archipelago.islands.iter().map(|island| island.amp(&cfg)).collect();
+                println!("mutate amp = {:?}", amps);
             }
         }
 
-        // 把保底的 N_GUARD 个上一轮个体也添加进去
-        for ind in &last_population[0..N_GUARD] {
-            new_generation.push(ind.clone());
+        // 按 save_schedule (或仪表盘的命令) 保存图像到文件. 总是以 (裁剪后、缩放前的) 原尺寸保存
+        let gen_best = archipelago.best();
+        let fitness = gen_best.get_fitness();
+        if force_save || cfg.save_schedule.is_due(gen, fitness, last_save.map(|(last_fitness, time)| (last_fitness, time.elapsed()))) {
+            last_save = Some((fitness, Instant::now()));
+            let stem = format!("{}/{}", save_folder, cfg.file_stem(gen, gen_best));
+            let metadata = genome_metadata(&cfg, gen, &target_hash);
+            save_best(&cfg, save_folder, &stem, &gen_best.rescaled(original_height, original_width), &metadata, cfg.save_genome);
+            if let Some(dashboard) = &mut dashboard {
+                dashboard.notify(format!("第 {} 轮已保存到 {}.png", gen, stem));
+            }
         }
 
-        // 计算所有个体的适应度
-        for ind in &mut new_generation {
-            ind.calc_fitness(&target);
+        // 检查停止条件
+        if fitness < best_fitness {
+            (best_fitness, stagnant_gens) = (fitness, 0);
+        } else {
+            stagnant_gens += 1;
         }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            break "收到 Ctrl-C (或从仪表盘退出)".to_string();
+        }
+        if let Some(reason) = cfg.stop_reason(gen, start.elapsed(), archipelago.best(), stagnant_gens) {
+            break reason;
+        }
+        gen += 1;
+    };
+
+    // 保存最终结果、检查点和延时动画
+    drop(dashboard);    // 先恢复终端
+    let final_best = archipelago.best().rescaled(original_height, original_width);
+    save_best(&cfg, save_folder, &format!("{}/final", save_folder), &final_best, &genome_metadata(&cfg, gen, &target_hash), true);
+    save_checkpoint(&cfg, save_folder, &archipelago.checkpoint(gen, cfg.seed));
+    if let Some(opts) = &cfg.timelapse {
+        anim::timelapse(save_folder, opts);     // 用这次运行保存的全部图片生成一次延时动画
+    }
+    RunSummary {
+        generations: gen,
+        seconds: start.elapsed().as_secs_f32(),
+        fitness: archipelago.best().get_fitness(),
+        n_shapes: final_best.n_shapes(),
+        stop_reason,
+    }
+}
+
 
-        // 把这一代的个体按照适应度进行排序, 留下前 POP_SIZE 个
-        new_generation.sort_by(|ind1, ind2| {
-            let f1 = ind1.get_fitness();
-            let f2 = ind2.get_fitness();
-            f1.partial_cmp(&f2).unwrap()
-        });
-        last_population = new_generation;
-        last_population.truncate(POP_SIZE);
+/// 保存 (已缩放到原尺寸的) 最优个体: {stem}.png, 以及按设置保存的 {stem}.json 和 {stem}.svg. 同时更新保存文件夹中的 best.json
+fn save_best(cfg: &Config, save_folder: &str, stem: &str, best: &Individual, metadata: &GenomeMetadata, save_genome: bool) {
+    best.draw_self().write_to_file(format!("{}.png", stem).as_str(), cfg.color_mode == ColorMode::Gray);
+    let json = best.to_json_with_metadata(metadata);
+    fs::write(format!("{}/best.json", save_folder), &json).unwrap();   // 供 polish 等后续处理使用
+    if save_genome {
+        fs::write(format!("{}.json", stem), &json).unwrap();
+        if cfg.save_binary {
+            match genome::encode_binary(best, Some(metadata), true) {
+                Ok(bytes) => fs::write(format!("{}.eag", stem), bytes).unwrap(),
+                Err(message) => eprintln!("无法保存 {}.eag: {}", stem, message),
+            }
+        }
+    }
+    if cfg.save_svg {
+        fs::write(format!("{}.svg", stem), best.to_svg()).unwrap();
+    }
+}
 
-        // 打印最优个体的适应度
-        let gen_best = &last_population.first().unwrap();
-        println!("best fitness = {}", gen_best.get_fitness());
-        println!("his n_shapes = {}", gen_best.n_shapes());
 
-        // 保存图像到文件 (考虑到越到后期越难进化, 保存频率逐渐降低)
-        if (gen <= 100) ||
-           (gen <= 1000 && gen % 10 == 0) ||
-           (gen <= 10000 && gen % 100 == 0) ||
-           (gen <= 100000 && gen % 1000 == 0) ||
-           (gen % 10000 == 0)
-        {
-            let canv = gen_best.draw_self();
-            canv.write_to_file(format!("{}/{}.png", save_folder, gen).as_str());
+/// 保存检查点: save_binary 时为 checkpoint.eag, 否则 (或二进制编码失败时) 为 checkpoint.json
+fn save_checkpoint(cfg: &Config, save_folder: &str, checkpoint: &Checkpoint) {
+    if cfg.save_binary {
+        match genome::encode_checkpoint(checkpoint, true) {
+            Ok(bytes) => return fs::write(format!("{}/checkpoint.eag", save_folder), bytes).unwrap(),
+            Err(message) => eprintln!("无法保存二进制检查点, 改为保存 JSON: {}", message),
         }
+    }
+    fs::write(format!("{}/checkpoint.json", save_folder), serde_json::to_string(checkpoint).unwrap()).unwrap();
+}
 
+
+/// 保存基因文件时记录的元数据: 轮数、时刻、随机数种子、目标图片的散列和完整配置
+fn genome_metadata(cfg: &Config, gen: usize, target_hash: &Option<String>) -> GenomeMetadata {
+    GenomeMetadata {
+        generation: Some(gen),
+        timestamp: Some(chrono::Local::now().to_rfc3339()),
+        seed: cfg.seed,
+        target_hash: target_hash.clone(),
+        config: Some(serde_json::to_value(cfg).unwrap()),
     }
+}
 
+
+/// 文件内容的散列, 文件无法读取时为 None
+fn hash_file(path: &str) -> Option<String> {
+    fs::read(path).ok().map(|bytes| genome::hash_bytes(&bytes))
 }
 
 
+/// 个体元数据中记录的原运行的配置, 没有或无法解析时为 None
+fn run_config(metadata: Option<&GenomeMetadata>) -> Option<Config> {
+    metadata.and_then(|metadata| metadata.config.clone())
+        .and_then(|config| serde_json::from_value(config).ok())
+}
 
+
+/// 与个体比对的目标图片: 按原运行的配置 (没有时为默认配置) 裁剪并设置比对方式, 再缩放到个体的尺寸.
+/// target_path 为 None 时使用原运行的目标图片
+fn target_for(ind: &Individual, run_config: Option<&Config>, target_path: Option<&str>) -> Canvas {
+    let mut cfg = run_config.cloned().unwrap_or_default();
+    if let Some(path) = target_path {
+        cfg.target = path.to_string();
+    }
+    cfg.max_dim = None;     // 直接缩放到个体的尺寸
+    let (x_height, y_width) = ind.env_size();
+    cfg.load_target().0.resized(x_height, y_width, cfg.resize_filter)
+}
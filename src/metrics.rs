@@ -0,0 +1,94 @@
+use std::fs;
+use std::io::{BufWriter, Write};
+use serde::*;
+use crate::util::Individual;
+use crate::evolve::StepStats;
+
+
+/// 指标日志的格式
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsFormat {
+    Csv,        // metrics.csv
+    Jsonl,      // metrics.jsonl, 每行一个 JSON 对象
+}
+
+
+/// 一轮演化的各项指标
+#[derive(Clone, Debug, Serialize)]
+pub struct MetricsRow {
+    pub gen: usize,                 // 轮数
+    pub wall_time: f64,             // 自开始演化经过的秒数
+    pub best_fitness: f32,
+    pub mean_fitness: f32,
+    pub worst_fitness: f32,
+    pub best_n_shapes: usize,       // 最优个体的图形数目
+    pub mean_n_shapes: f32,         // 全部个体的平均图形数目
+    pub acceptance_rate: f32,       // 变异的接受率 (优于亲代的比例)
+    pub evals_per_sec: f32,         // 这一轮每秒计算适应度的次数
+}
+impl MetricsRow {
+    const CSV_HEADER: &'static str = "gen,wall_time,best_fitness,mean_fitness,worst_fitness,best_n_shapes,mean_n_shapes,acceptance_rate,evals_per_sec";
+
+    /// 由这一轮的全部个体 (至少一个) 和统计数据计算指标. step_secs 为这一轮所用的秒数
+    pub fn new<'a>(gen: usize, wall_time: f64, individuals: impl Iterator<Item = &'a Individual>, stats: &StepStats, step_secs: f64) -> MetricsRow {
+        let (mut best, mut sum_fitness, mut worst, mut sum_shapes, mut n) = (None::<&Individual>, 0.0, f32::NEG_INFINITY, 0, 0);
+        for ind in individuals {
+            let fitness = ind.get_fitness();
+            if best.is_none_or(|best| fitness < best.get_fitness()) { best = Some(ind) }
+            worst = worst.max(fitness);
+            sum_fitness += fitness;
+            sum_shapes += ind.n_shapes();
+            n += 1;
+        }
+        let best = best.expect("至少需要一个个体!");
+        MetricsRow {
+            gen,
+            wall_time,
+            best_fitness: best.get_fitness(),
+            mean_fitness: sum_fitness / n as f32,
+            worst_fitness: worst,
+            best_n_shapes: best.n_shapes(),
+            mean_n_shapes: sum_shapes as f32 / n as f32,
+            acceptance_rate: stats.acceptance_rate(),
+            evals_per_sec: if step_secs > 0.0 { (stats.n_evals as f64 / step_secs) as f32 } else { 0.0 },
+        }
+    }
+
+    /// CSV 中的一行
+    fn to_csv(&self) -> String {
+        format!("{},{:.3},{},{},{},{},{},{},{:.1}", self.gen, self.wall_time, self.best_fitness, self.mean_fitness, self.worst_fitness,
+                self.best_n_shapes, self.mean_n_shapes, self.acceptance_rate, self.evals_per_sec)
+    }
+}
+
+
+/// 逐轮写入指标的日志文件. 每写一行都立即刷新, 便于在运行中绘图
+pub struct MetricsLog {
+    writer: BufWriter<fs::File>,
+    format: MetricsFormat,
+}
+impl MetricsLog {
+    /// 在 folder 中新建 metrics.csv 或 metrics.jsonl
+    pub fn create(folder: &str, format: MetricsFormat) -> MetricsLog {
+        let path = match format {
+            MetricsFormat::Csv => format!("{}/metrics.csv", folder),
+            MetricsFormat::Jsonl => format!("{}/metrics.jsonl", folder),
+        };
+        let mut writer = BufWriter::new(fs::File::create(path).unwrap());
+        if format == MetricsFormat::Csv {
+            writeln!(writer, "{}", MetricsRow::CSV_HEADER).unwrap();
+        }
+        MetricsLog { writer, format }
+    }
+
+    /// 写入一轮的指标
+    pub fn write(&mut self, row: &MetricsRow) {
+        match self.format {
+            MetricsFormat::Csv => writeln!(self.writer, "{}", row.to_csv()).unwrap(),
+            MetricsFormat::Jsonl => writeln!(self.writer, "{}", serde_json::to_string(row).unwrap()).unwrap(),
+        }
+        self.writer.flush().unwrap();
+    }
+}
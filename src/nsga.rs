@@ -0,0 +1,136 @@
+use crate::config::Config;
+use crate::evolve::spawn_child;
+use crate::util::*;
+
+
+/// NSGA-II 多目标演化: 同时最小化适应度 (l2_diff) 和图形数目, 维护二者的 Pareto 前沿
+pub struct ParetoPopulation {
+    pub individuals: Vec<Individual>,
+    ranks: Vec<usize>,          // 非支配排序的层级, 0 为 Pareto 前沿
+    crowding: Vec<f32>,         // 拥挤距离, 越大越稀疏
+}
+impl ParetoPopulation {
+    /// 创建最初的随机种群
+    pub fn new(target: &Canvas, cfg: &Config) -> ParetoPopulation {
+        let individuals: Vec<Individual> = (0..cfg.pareto_pop_size).map(|_| {
+            let mut ind = cfg.new_individual(target);
+            ind.calc_fitness(target);
+            ind
+        }).collect();
+        let (ranks, crowding) = rank_and_crowding(&individuals);
+        ParetoPopulation { individuals, ranks, crowding }
+    }
+
+    /// 演化一轮: 二元锦标赛选出亲代产仔 (图形颜色受 colors 约束), 亲子合并后按 (层级, 拥挤距离) 保留 pareto_pop_size 个
+    pub fn step(&mut self, target: &Canvas, cfg: &Config, colors: &ColorModel) {
+        let n = self.individuals.len();
+        let mut merged: Vec<Individual> = Vec::with_capacity(2 * n);
+        for _ in 0..n {
+            let parent = &self.individuals[self.binary_tournament()];
+            let (mut child, _) = spawn_child(parent, target, cfg, cfg.mutate_amp, colors);
+            child.calc_fitness(target);
+            merged.push(child);
+        }
+        merged.append(&mut self.individuals);
+
+        let (ranks, crowding) = rank_and_crowding(&merged);
+        let mut order: Vec<usize> = (0..merged.len()).collect();
+        order.sort_by(|&i, &j| {
+            ranks[i].cmp(&ranks[j]).then(crowding[j].partial_cmp(&crowding[i]).unwrap())
+        });
+        order.truncate(cfg.pareto_pop_size);
+
+        let mut slots: Vec<Option<Individual>> = merged.into_iter().map(Some).collect();
+        self.individuals = order.iter().map(|&i| slots[i].take().unwrap()).collect();
+        self.ranks = order.iter().map(|&i| ranks[i]).collect();
+        self.crowding = order.iter().map(|&i| crowding[i]).collect();
+    }
+
+    /// 当前的 Pareto 前沿, 每种图形数目只保留适应度最优的一个, 按图形数目从少到多排列
+    pub fn front(&self) -> Vec<&Individual> {
+        let mut front: Vec<&Individual> = self.individuals.iter().zip(&self.ranks)
+            .filter(|(_, &rank)| rank == 0)
+            .map(|(ind, _)| ind)
+            .collect();
+        front.sort_by(|ind1, ind2| {
+            ind1.n_shapes().cmp(&ind2.n_shapes()).then(ind1.get_fitness().partial_cmp(&ind2.get_fitness()).unwrap())
+        });
+        front.dedup_by_key(|ind| ind.n_shapes());
+        front
+    }
+
+    /// 随机抽取两个个体, 返回 (层级, 拥挤距离) 更优者的下标
+    fn binary_tournament(&self) -> usize {
+        let n = self.individuals.len();
+        let (i, j) = (random::randint(0, n), random::randint(0, n));
+        if self.ranks[i] != self.ranks[j] {
+            if self.ranks[i] < self.ranks[j] { i } else { j }
+        } else if self.crowding[i] >= self.crowding[j] { i } else { j }
+    }
+}
+
+
+/// 两个目标: 适应度和图形数目, 都是越小越好
+fn objectives(ind: &Individual) -> [f32; 2] {
+    [ind.get_fitness(), ind.n_shapes() as f32]
+}
+
+
+/// a 是否支配 b: 每个目标都不差于 b, 且至少一个目标严格优于 b
+fn pareto_dominates(a: &[f32; 2], b: &[f32; 2]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x <= y) && a.iter().zip(b).any(|(x, y)| x < y)
+}
+
+
+/// 快速非支配排序, 并在每一层内计算拥挤距离. 返回每个个体的 (层级, 拥挤距离)
+#[allow(clippy::needless_range_loop)]
+fn rank_and_crowding(individuals: &[Individual]) -> (Vec<usize>, Vec<f32>) {
+    let n = individuals.len();
+    let objs: Vec<[f32; 2]> = individuals.iter().map(objectives).collect();
+
+    // 非支配排序
+    let mut dominates: Vec<Vec<usize>> = vec![Vec::new(); n];       // i 支配的全部个体
+    let mut n_dominating = vec![0usize; n];                         // 支配 i 的个体数
+    for i in 0..n {
+        for j in 0..n {
+            if pareto_dominates(&objs[i], &objs[j]) {
+                dominates[i].push(j);
+            } else if pareto_dominates(&objs[j], &objs[i]) {
+                n_dominating[i] += 1;
+            }
+        }
+    }
+    let mut ranks = vec![0usize; n];
+    let mut fronts: Vec<Vec<usize>> = vec![(0..n).filter(|&i| n_dominating[i] == 0).collect()];
+    while let Some(current) = fronts.last().filter(|front| !front.is_empty()).cloned() {
+        let mut next = Vec::new();
+        for i in current {
+            for &j in &dominates[i] {
+                n_dominating[j] -= 1;
+                if n_dominating[j] == 0 {
+                    ranks[j] = fronts.len();
+                    next.push(j);
+                }
+            }
+        }
+        fronts.push(next);
+    }
+
+    // 拥挤距离: 每个目标上相邻两个个体的归一化间距之和, 边界个体为无穷大
+    let mut crowding = vec![0.0f32; n];
+    for front in &fronts {
+        for m in 0..2 {
+            let mut sorted = front.clone();
+            sorted.sort_by(|&i, &j| objs[i][m].partial_cmp(&objs[j][m]).unwrap());
+            let (Some(&first), Some(&last)) = (sorted.first(), sorted.last()) else { continue };
+            let range = objs[last][m] - objs[first][m];
+            crowding[first] = f32::INFINITY;
+            crowding[last] = f32::INFINITY;
+            if range <= 0.0 { continue }
+            for k in 1..sorted.len().saturating_sub(1) {
+                crowding[sorted[k]] += (objs[sorted[k + 1]][m] - objs[sorted[k - 1]][m]) / range;
+            }
+        }
+    }
+    (ranks, crowding)
+}
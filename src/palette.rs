@@ -0,0 +1,64 @@
+use crate::util::*;
+
+
+/// 解析 "#rrggbb", "rrggbb" 或 "#rgb" 格式的十六进制颜色
+pub fn parse_hex(hex: &str) -> Option<(f32, f32, f32)> {
+    let hex = hex.trim().trim_start_matches('#');
+    if !hex.is_ascii() { return None }     // 按字节切片, 非 ASCII 字符会切在字符中间
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok().map(|v| v as f32);
+    match hex.len() {
+        6 => Some((channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?)),
+        3 => {
+            // "#abc" 即 "#aabbcc"
+            let short = |i: usize| channel(&hex[i..i + 1]).map(|v| v * 17.0);
+            Some((short(0)?, short(1)?, short(2)?))
+        }
+        _ => None,
+    }
+}
+
+
+/// 用 k-means 将 colors 聚为至多 k 类, 返回各类的中心
+pub fn kmeans(colors: &[(f32, f32, f32)], k: usize, n_iters: usize) -> Vec<(f32, f32, f32)> {
+    if colors.is_empty() || k == 0 { return Vec::new() }
+    let dist = |c1: &(f32, f32, f32), c2: &(f32, f32, f32)| {
+        (c1.0 - c2.0).powi(2) + (c1.1 - c2.1).powi(2) + (c1.2 - c2.2).powi(2)
+    };
+    let nearest = |centers: &[(f32, f32, f32)], c: &(f32, f32, f32)| {
+        (0..centers.len()).min_by(|&i, &j| dist(&centers[i], c).partial_cmp(&dist(&centers[j], c)).unwrap()).unwrap()
+    };
+
+    // k-means++ 初始化: 依次按照到已有中心距离的平方为权重抽取新的中心
+    let mut centers = vec![colors[random::randint(0, colors.len())]];
+    while centers.len() < k {
+        let weights: Vec<f32> = colors.iter().map(|c| dist(&centers[nearest(&centers, c)], c)).collect();
+        if weights.iter().sum::<f32>() <= 0.0 { break }     // 不同的颜色已经不足 k 种
+        let indices: Vec<usize> = (0..colors.len()).collect();
+        centers.push(colors[random::weighted_choice(&indices, &weights)]);
+    }
+
+    // 交替进行分配和更新
+    for _ in 0..n_iters {
+        let mut sums = vec![(0.0, 0.0, 0.0, 0usize); centers.len()];
+        for c in colors {
+            let sum = &mut sums[nearest(&centers, c)];
+            sum.0 += c.0;
+            sum.1 += c.1;
+            sum.2 += c.2;
+            sum.3 += 1;
+        }
+        let new_centers: Vec<(f32, f32, f32)> = sums.iter().zip(&centers)
+            .map(|(&(r, g, b, n), &center)| if n == 0 { center } else { (r / n as f32, g / n as f32, b / n as f32) })
+            .collect();
+        if new_centers == centers { break }
+        centers = new_centers;
+    }
+    centers
+}
+
+
+/// 从图片中提取至多 k 种颜色的调色板
+pub fn extract(canvas: &Canvas, k: usize) -> Vec<(f32, f32, f32)> {
+    const MAX_SAMPLES: usize = 20000;   // 参与聚类的像素数上限
+    kmeans(&canvas.opaque_colors(MAX_SAMPLES), k, 50)
+}
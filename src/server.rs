@@ -0,0 +1,239 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use image::ImageEncoder;
+use crate::config::Config;
+use crate::util::Individual;
+
+
+/// 运行中修改参数的请求: 参数补丁, 以及回复结果 (新配置的 JSON 或错误信息) 的通道
+pub type ConfigPatch = (serde_json::Value, mpsc::Sender<Result<String, String>>);
+
+
+/// 演化线程最新发布的状态
+#[derive(Default)]
+struct Snapshot {
+    gen: usize,
+    best: Option<Individual>,
+    config_json: String,
+}
+
+
+/// 只监听 127.0.0.1 的 HTTP 服务. 各连接在各自的线程中处理, 与演化线程之间通过 Snapshot 和 ConfigPatch 通道交换数据.
+/// 为防止浏览器中的其他网页 (跨站请求、DNS rebinding) 访问, 只接受 Host 为本机该端口、Origin (若有) 与之一致的请求,
+/// POST 须为 Content-Type: application/json (浏览器跨站发送时需要预检, 而本服务不响应预检)
+///   GET  /            简单的网页: 实时预览、进度和参数修改
+///   GET  /best.png    当前最优个体 (演化分辨率下) 的图片
+///   GET  /best.json   当前最优个体的基因
+///   GET  /config      当前的配置
+///   POST /config      以 JSON 对象修改 Config::TUNABLE_FIELDS 中的参数, 如 {"mutate_ratio": 0.2, "pop_size": 8}
+///   GET  /events      Server-Sent Events, 推送每一轮的进度 {"gen", "fitness", "n_shapes"}
+pub struct Server {
+    snapshot: Arc<Mutex<Snapshot>>,
+    patches: mpsc::Receiver<ConfigPatch>,
+}
+impl Server {
+    const EVENT_INTERVAL: Duration = Duration::from_millis(200);   // SSE 推送进度的间隔
+    const PATCH_TIMEOUT: Duration = Duration::from_secs(10);       // 等待演化线程应用参数的最长时间
+    const MAX_BODY: usize = 64 * 1024;                              // 请求体的最大字节数, 超过时回复 413
+
+    /// 在 127.0.0.1:port 上启动服务
+    pub fn start(port: u16, cfg: &Config) -> Server {
+        let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| panic!("无法监听端口 {}: {}", port, e));
+        let snapshot = Arc::new(Mutex::new(Snapshot { config_json: serde_json::to_string(cfg).unwrap(), ..Default::default() }));
+        let (patch_sender, patches) = mpsc::channel();
+        let shared = Arc::clone(&snapshot);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (snapshot, patch_sender) = (Arc::clone(&shared), patch_sender.clone());
+                thread::spawn(move || handle(stream, port, &snapshot, &patch_sender));
+            }
+        });
+        println!("HTTP 服务已启动: http://127.0.0.1:{}/", port);
+        Server { snapshot, patches }
+    }
+
+    /// 发布第 gen 轮的最优个体和当前配置
+    pub fn publish(&self, gen: usize, best: &Individual, cfg: &Config) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        snapshot.gen = gen;
+        snapshot.best = Some(best.clone());
+        snapshot.config_json = serde_json::to_string(cfg).unwrap();
+    }
+
+    /// 取出全部尚未处理的参数修改请求 (不阻塞). 处理后须通过请求中的通道回复
+    pub fn pending_patches(&self) -> Vec<ConfigPatch> {
+        self.patches.try_iter().collect()
+    }
+}
+
+
+/// 处理一个连接上的一个请求
+fn handle(stream: TcpStream, port: u16, snapshot: &Mutex<Snapshot>, patch_sender: &mpsc::Sender<ConfigPatch>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+
+    // 请求行和请求头
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() { return }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("").split('?').next().unwrap_or("");    // 忽略查询字符串 (如网页用于防缓存的 /best.png?gen)
+    let mut content_length = 0;
+    let (mut host, mut origin, mut content_type) = (None, None, None);
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() { break }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim().to_ascii_lowercase(), value.trim().to_string());
+            match name.as_str() {
+                "content-length" => content_length = value.parse().unwrap_or(0),
+                "host" => host = Some(value),
+                "origin" => origin = Some(value),
+                "content-type" => content_type = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    // 只接受发往本机该端口的请求, 以及来自本服务网页 (或非浏览器客户端) 的请求
+    let local_hosts = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    let host_ok = host.is_some_and(|host| local_hosts.contains(&host));
+    let origin_ok = origin.is_none_or(|origin| local_hosts.iter().any(|host| origin == format!("http://{}", host)));
+    let is_json = content_type.is_some_and(|content_type| {
+        content_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("application/json")
+    });
+
+    let response = match (method, path) {
+        _ if !host_ok || !origin_ok => respond(403, "text/plain; charset=utf-8", "只接受来自本机的请求".as_bytes()),
+        ("GET", "/") => respond(200, "text/html; charset=utf-8", INDEX_HTML.as_bytes()),
+        ("GET", "/best.png") => {
+            let best = snapshot.lock().unwrap().best.clone();     // 先取出个体并释放锁, 编码期间不阻塞演化线程
+            match best {
+                Some(best) => respond(200, "image/png", &encode_png(&best)),
+                None => respond(404, "text/plain; charset=utf-8", "尚无个体".as_bytes()),
+            }
+        }
+        ("GET", "/best.json") => match &snapshot.lock().unwrap().best {
+            Some(best) => respond(200, "application/json", best.to_json().as_bytes()),
+            None => respond(404, "text/plain; charset=utf-8", "尚无个体".as_bytes()),
+        },
+        ("GET", "/config") => respond(200, "application/json", snapshot.lock().unwrap().config_json.as_bytes()),
+        ("POST", "/config") if !is_json => {
+            respond(415, "text/plain; charset=utf-8", "请求体须为 Content-Type: application/json".as_bytes())
+        }
+        ("POST", "/config") if content_length > Server::MAX_BODY => {
+            respond(413, "text/plain; charset=utf-8", format!("请求体不能超过 {} 字节", Server::MAX_BODY).as_bytes())
+        }
+        ("POST", "/config") => {
+            let mut body = vec![0; content_length];
+            if reader.read_exact(&mut body).is_err() { return }
+            match serde_json::from_slice(&body) {
+                Ok(patch) => {
+                    let (reply_sender, reply) = mpsc::channel();
+                    patch_sender.send((patch, reply_sender)).unwrap();
+                    match reply.recv_timeout(Server::PATCH_TIMEOUT) {
+                        Ok(Ok(config_json)) => respond(200, "application/json", config_json.as_bytes()),
+                        Ok(Err(message)) => respond(400, "text/plain; charset=utf-8", message.as_bytes()),
+                        Err(_) => respond(503, "text/plain; charset=utf-8", "演化线程没有响应".as_bytes()),
+                    }
+                }
+                Err(e) => respond(400, "text/plain; charset=utf-8", format!("不是合法的 JSON: {}", e).as_bytes()),
+            }
+        }
+        ("GET", "/events") => {
+            stream_events(stream, snapshot);
+            return;
+        }
+        _ => respond(404, "text/plain; charset=utf-8", "Not Found".as_bytes()),
+    };
+    let _ = stream.write_all(&response);
+}
+
+
+/// 持续推送进度, 直到客户端断开
+fn stream_events(mut stream: TcpStream, snapshot: &Mutex<Snapshot>) {
+    let header = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+    if stream.write_all(header.as_bytes()).is_err() { return }
+    let mut last_gen = 0;
+    loop {
+        let event = {
+            let snapshot = snapshot.lock().unwrap();
+            match &snapshot.best {
+                Some(best) if snapshot.gen != last_gen => {
+                    last_gen = snapshot.gen;
+                    Some(serde_json::json!({ "gen": snapshot.gen, "fitness": best.get_fitness(), "n_shapes": best.n_shapes() }))
+                }
+                _ => None,
+            }
+        };
+        if let Some(event) = event {
+            if stream.write_all(format!("data: {}\n\n", event).as_bytes()).is_err() { return }
+        }
+        thread::sleep(Server::EVENT_INTERVAL);
+    }
+}
+
+
+/// 组装一个完整的 HTTP 响应
+fn respond(status: u16, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        _ => "Service Unavailable",
+    };
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status, reason, content_type, body.len()
+    ).into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+
+/// 将个体绘制并编码为 PNG
+fn encode_png(ind: &Individual) -> Vec<u8> {
+    let image = ind.draw_self().to_image();
+    let mut bytes = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut bytes)
+        .write_image(image.as_raw(), image.width(), image.height(), image::ColorType::Rgba8)
+        .unwrap();
+    bytes
+}
+
+
+/// 内置的网页: 收到进度事件时刷新预览, 并可提交参数修改
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>EARtist</title></head>
+<body style="font-family: sans-serif">
+<h2>EARtist</h2>
+<img id="best" src="/best.png" style="max-width: 45vw; image-rendering: pixelated; float: left; margin-right: 2em">
+<p id="progress">等待中...</p>
+<p>修改参数 (JSON):</p>
+<textarea id="patch" rows="6" cols="50">{"mutate_ratio": 0.1}</textarea><br>
+<button onclick="submitPatch()">提交</button>
+<pre id="result"></pre>
+<script>
+let loading = false;
+const best = document.getElementById("best");
+best.onload = best.onerror = () => { loading = false; };
+new EventSource("/events").onmessage = (e) => {
+    const p = JSON.parse(e.data);
+    document.getElementById("progress").textContent = `第 ${p.gen} 轮, fitness = ${p.fitness.toFixed(4)}, n_shapes = ${p.n_shapes}`;
+    if (!loading) { loading = true; best.src = "/best.png?" + p.gen; }
+};
+async function submitPatch() {
+    const response = await fetch("/config", { method: "POST", headers: { "Content-Type": "application/json" }, body: document.getElementById("patch").value });
+    document.getElementById("result").textContent = response.status + "\n" + await response.text();
+}
+</script>
+</body>
+</html>
+"#;
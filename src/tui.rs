@@ -0,0 +1,191 @@
+use std::io::{stdout, Stdout, Write};
+use std::time::{Duration, Instant};
+use crossterm::{cursor, event, queue, style, terminal};
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use crate::util::{Filter, Individual};
+
+
+/// 仪表盘上的按键命令
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    Pause,      // 空格 / p: 暂停或继续
+    Save,       // s: 立即保存当前最优个体
+    Prune,      // r: 修剪最优个体多余的图形
+    Quit,       // q / Esc / Ctrl-C: 结束演化
+}
+
+
+/// 一次刷新时展示的运行状态
+pub struct Status<'a> {
+    pub gen: usize,
+    pub best: &'a Individual,
+    pub resolution: (usize, usize),     // 当前演化所用的分辨率
+    pub diversity: f32,                 // 种群内两两个体的平均基因差异
+    pub acceptance_rate: f32,           // 最近一轮变异的接受率
+    pub amps: Option<Vec<f32>>,         // 各岛屿的变异剧烈程度 (开启 adaptive_amp 时)
+}
+
+
+/// 终端仪表盘: 最优个体的半块字符预览、适应度曲线和各项统计. 存在期间终端处于备用屏幕和原始模式, 析构时恢复
+pub struct Dashboard {
+    out: Stdout,
+    history: Vec<f32>,          // 每一轮的最优适应度
+    message: String,            // 最近一条提示信息
+    last_draw: Option<(Instant, usize)>,    // 上次刷新的时刻和当时已记录的轮数, 用于计算速度
+    gens_per_sec: f32,
+    pub paused: bool,
+}
+impl Dashboard {
+    const REFRESH_INTERVAL: Duration = Duration::from_millis(100);     // 刷新间隔, 避免拖慢演化
+    const CHART_HEIGHT: u16 = 10;                                       // 适应度曲线的行数
+
+    /// 进入备用屏幕和原始模式
+    pub fn open() -> Dashboard {
+        let mut out = stdout();
+        terminal::enable_raw_mode().unwrap();
+        queue!(out, terminal::EnterAlternateScreen, cursor::Hide).unwrap();
+        out.flush().unwrap();
+        Dashboard { out, history: Vec::new(), message: String::new(), last_draw: None, gens_per_sec: 0.0, paused: false }
+    }
+
+    /// 记录一轮的最优适应度
+    pub fn record(&mut self, best_fitness: f32) {
+        self.history.push(best_fitness);
+    }
+
+    /// 设置提示信息 (显示在统计数据下方)
+    pub fn notify(&mut self, message: String) {
+        self.message = message;
+    }
+
+    /// 取出全部尚未处理的按键命令 (不阻塞)
+    pub fn poll(&mut self) -> Vec<Command> {
+        let mut commands = Vec::new();
+        while event::poll(Duration::ZERO).unwrap() {
+            let Event::Key(key) = event::read().unwrap() else { continue };
+            if key.kind != KeyEventKind::Press { continue }
+            let ctrl_c = key.modifiers.contains(event::KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+            let command = match key.code {
+                _ if ctrl_c => Command::Quit,
+                KeyCode::Char(' ') | KeyCode::Char('p') => Command::Pause,
+                KeyCode::Char('s') => Command::Save,
+                KeyCode::Char('r') => Command::Prune,
+                KeyCode::Char('q') | KeyCode::Esc => Command::Quit,
+                _ => continue,
+            };
+            commands.push(command);
+        }
+        commands
+    }
+
+    /// 距上次刷新是否已经过了 REFRESH_INTERVAL (收集 Status 的开销不小, 应先检查)
+    pub fn is_due(&self) -> bool {
+        self.last_draw.is_none_or(|(time, _)| time.elapsed() >= Self::REFRESH_INTERVAL)
+    }
+
+    /// 刷新画面
+    pub fn draw(&mut self, status: &Status) {
+        if let Some((time, n_gens)) = self.last_draw {
+            let secs = time.elapsed().as_secs_f32();
+            if secs > 0.0 && self.history.len() > n_gens {
+                self.gens_per_sec = (self.history.len() - n_gens) as f32 / secs;
+            }
+        }
+        self.last_draw = Some((Instant::now(), self.history.len()));
+
+        let (cols, rows) = terminal::size().unwrap();
+        queue!(self.out, style::ResetColor, terminal::Clear(terminal::ClearType::All)).unwrap();
+
+        // 左半边: 最优个体的预览
+        let preview_width = self.draw_preview(status.best, cols / 2, rows);
+
+        // 右半边: 统计数据、适应度曲线和按键说明
+        let left = preview_width + 2;
+        let width = cols.saturating_sub(left + 1).max(10);
+        let mut lines = vec![
+            format!("第 {} 轮{}", status.gen, if self.paused { " (已暂停)" } else { "" }),
+            format!("best fitness = {:.4}", status.best.get_fitness()),
+            format!("n_shapes     = {}", status.best.n_shapes()),
+            format!("分辨率       = {}×{}", status.resolution.0, status.resolution.1),
+            format!("速度         = {:.1} 轮/秒", self.gens_per_sec),
+            format!("多样性       = {:.4}", status.diversity),
+            format!("接受率       = {:.3}", status.acceptance_rate),
+        ];
+        if let Some(amps) = &status.amps {
+            lines.push(format!("mutate amp   = {:?}", amps.iter().map(|amp| (amp * 1000.0).round() / 1000.0).collect::<Vec<f32>>()));
+        }
+        lines.push(String::new());
+        for (row, line) in lines.iter().enumerate() {
+            queue!(self.out, cursor::MoveTo(left, row as u16), style::Print(line)).unwrap();
+        }
+        let chart_top = lines.len() as u16;
+        self.draw_chart(left, chart_top, width.saturating_sub(10).max(1), Self::CHART_HEIGHT);     // 右侧留出标注最大最小值的空间
+        let help_top = chart_top + Self::CHART_HEIGHT + 1;
+        queue!(self.out,
+            style::ResetColor,
+            cursor::MoveTo(left, help_top), style::Print(&self.message),
+            cursor::MoveTo(left, help_top + 2), style::Print("[空格] 暂停/继续  [s] 保存  [r] 修剪  [q] 退出"),
+        ).unwrap();
+        self.out.flush().unwrap();
+    }
+
+    /// 在左上角用半块字符 (▀, 前景色为上半格, 背景色为下半格) 画出个体的缩略图, 返回所占的列数
+    fn draw_preview(&mut self, ind: &Individual, max_cols: u16, max_rows: u16) -> u16 {
+        let (height, width) = ind.env_size();
+        let ratio = (max_cols as f32 / width as f32).min(2.0 * max_rows as f32 / height as f32);
+        let preview_width = ((width as f32 * ratio) as usize).max(1);
+        let preview_height = ((height as f32 * ratio) as usize).max(2) & !1;    // 取偶数, 每行字符对应两行像素
+        let image = ind.draw_self().resized(preview_height, preview_width, Filter::Triangle).to_image();
+        // 半透明的像素画在黑色上
+        let color_at = |i: usize, j: usize| {
+            let [r, g, b, a] = image.get_pixel(j as u32, i as u32).0;
+            let blend = |c: u8| (c as f32 * a as f32 / 255.0) as u8;
+            style::Color::Rgb { r: blend(r), g: blend(g), b: blend(b) }
+        };
+        for row in 0..preview_height / 2 {
+            queue!(self.out, cursor::MoveTo(0, row as u16)).unwrap();
+            for j in 0..preview_width {
+                queue!(self.out,
+                    style::SetForegroundColor(color_at(2 * row, j)),
+                    style::SetBackgroundColor(color_at(2 * row + 1, j)),
+                    style::Print('▀'),
+                ).unwrap();
+            }
+        }
+        queue!(self.out, style::ResetColor).unwrap();
+        preview_width as u16
+    }
+
+    /// 以 (left, top) 为左上角, 画出 width × height 字符的最优适应度曲线 (全部历史均匀抽样到 width 列)
+    fn draw_chart(&mut self, left: u16, top: u16, width: u16, height: u16) {
+        const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        if self.history.is_empty() { return }
+        let n = self.history.len();
+        let n_cols = (width as usize).min(n);
+        let samples: Vec<f32> = (0..n_cols).map(|k| self.history[k * (n - 1) / (n_cols - 1).max(1)]).collect();
+        let (lo, hi) = samples.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let range = (hi - lo).max(f32::EPSILON);
+        // 每列的高度, 以 1/8 字符为单位
+        let levels: Vec<usize> = samples.iter()
+            .map(|&v| ((v - lo) / range * (height as f32 * 8.0 - 1.0)).round() as usize + 1)
+            .collect();
+        for row in 0..height {
+            let line: String = levels.iter().map(|&level| {
+                let filled = level.saturating_sub(8 * (height - 1 - row) as usize);
+                if filled == 0 { ' ' } else { BLOCKS[filled.min(8) - 1] }
+            }).collect();
+            queue!(self.out, cursor::MoveTo(left, top + row), style::SetForegroundColor(style::Color::Green), style::Print(line)).unwrap();
+        }
+        queue!(self.out, style::ResetColor,
+            cursor::MoveTo(left + n_cols as u16 + 1, top), style::Print(format!("{:.3}", hi)),
+            cursor::MoveTo(left + n_cols as u16 + 1, top + height - 1), style::Print(format!("{:.3}", lo)),
+        ).unwrap();
+    }
+}
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let _ = queue!(self.out, style::ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = self.out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -1,31 +1,54 @@
 use std::cmp::{min, max};
 use std::fmt::Write;
 use serde::*;
+use crate::genome::{self, GenomeMetadata};
 
 
 pub mod random {
-    use rand::{Rng, thread_rng};
+    use std::cell::RefCell;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    thread_local! {
+        // 每个线程各自的随机数生成器. 默认以系统熵初始化, 可用 seed 重新设定
+        static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+    }
+
+    /// 以 seed 重新初始化当前线程的随机数生成器, 之后的随机序列可以复现
+    pub fn seed(seed: u64) {
+        RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+    }
+
+    /// 由系统熵生成一个新的种子 (不受 seed 影响)
+    pub fn fresh_seed() -> u64 {
+        rand::random()
+    }
+
+    /// 从当前线程的随机数生成器中派生一个种子, 供子线程使用
+    pub fn derive_seed() -> u64 {
+        RNG.with(|rng| rng.borrow_mut().gen())
+    }
 
     /// 生成 U(low, high) 随机浮点数
     pub fn uniform(low: f32, high: f32) -> f32 {
-        thread_rng().gen_range(low, high)
+        RNG.with(|rng| rng.borrow_mut().gen_range(low, high))
     }
 
     /// 生成 N(0, sigma) 随机数
     pub fn normal(sigma: f32) -> f32 {
-        thread_rng().sample::<f32, _>(rand_distr::StandardNormal) * sigma
+        RNG.with(|rng| rng.borrow_mut().sample::<f32, _>(rand_distr::StandardNormal)) * sigma
     }
 
     /// 生成 [i, j) 随机整数
     pub fn randint<T: rand_distr::uniform::SampleUniform>(low: T, high: T) -> T {
-        thread_rng().gen_range(low, high)
+        RNG.with(|rng| rng.borrow_mut().gen_range(low, high))
     }
 
     /// 按照权重随机抽取一个
     pub fn weighted_choice<T: Clone + Copy>(items: &[T], weight: &[f32]) -> T {
         debug_assert!(weight.iter().all(|w| { *w >= 0.0 }) && weight.iter().sum::<f32>() > 0.0);   // 权重非负、不能全 0
         debug_assert!(items.len() == weight.len());         // 待抽签的 list 和权重 list 必须等长
-        let mut accumulate = weight.clone().to_vec();
+        let mut accumulate = weight.to_vec();
         let mut curr_sum = weight[0];
         for i in 1..accumulate.len() {
             curr_sum += weight[i];
@@ -41,6 +64,48 @@ pub mod random {
         panic!("Unreachable!");
     }
 }
+
+
+/// 按照 1/5 成功法则自适应调整变异幅度:
+/// 每累计 window 次变异, 若成功 (子代优于亲代) 比例高于 1/5 则放大幅度, 低于 1/5 则缩小幅度
+pub struct AdaptiveAmp {
+    amp: f32,
+    n_success: usize,
+    n_trial: usize,
+    window: usize,
+}
+impl AdaptiveAmp {
+    const FACTOR: f32 = 1.22;                   // 每次调整的倍率
+    const AMP_RANGE: (f32, f32) = (0.01, 10.0); // 幅度的上下限, 防止塌缩到 0 或发散
+
+    pub fn new(init_amp: f32, window: usize) -> AdaptiveAmp {
+        debug_assert!(window > 0);
+        AdaptiveAmp { amp: init_amp, n_success: 0, n_trial: 0, window }
+    }
+
+    /// 当前的变异幅度
+    pub fn amp(&self) -> f32 {
+        self.amp
+    }
+
+    /// 记录一次变异的结果, 满 window 次后调整幅度
+    pub fn record(&mut self, success: bool) {
+        self.n_trial += 1;
+        if success { self.n_success += 1; }
+        if self.n_trial < self.window { return }
+        let success_rate = self.n_success as f32 / self.n_trial as f32;
+        if success_rate > 0.2 {
+            self.amp *= Self::FACTOR;
+        } else if success_rate < 0.2 {
+            self.amp /= Self::FACTOR;
+        }
+        self.amp = self.amp.clamp(Self::AMP_RANGE.0, Self::AMP_RANGE.1);
+        self.n_success = 0;
+        self.n_trial = 0;
+    }
+}
+
+
 trait MutatableFloat {
     fn mutate(self, sigma: f32, min: f32, max: f32) -> Self;
 }
@@ -51,33 +116,120 @@ impl MutatableFloat for f32 {
 }
 
 
-/// RGBA 颜色类
+/// 图形颜色的取值约束
+#[derive(Clone, Debug)]
+pub enum ColorModel {
+    Free,                               // RGB 可任意取值
+    Palette(Vec<(f32, f32, f32)>),      // RGB 只能取调色板中的颜色, 变异时在调色板中切换
+    Gray,                               // 灰度: r = g = b, 只演化一个灰度值
+    Ink(f32, f32, f32),                 // 单色: RGB 固定为这一种墨水色, 只演化透明度
+}
+impl ColorModel {
+    /// 从调色板中随机抽取一个颜色
+    fn rand_palette_color(palette: &[(f32, f32, f32)]) -> (f32, f32, f32) {
+        palette[random::randint(0, palette.len())]
+    }
+
+    /// 调色板中与 (r, g, b) 最接近的颜色
+    fn nearest_palette_color(palette: &[(f32, f32, f32)], r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+        let dist = |c: &&(f32, f32, f32)| (c.0 - r).powi(2) + (c.1 - g).powi(2) + (c.2 - b).powi(2);
+        *palette.iter().min_by(|c1, c2| dist(c1).partial_cmp(&dist(c2)).unwrap()).unwrap()
+    }
+}
+
+
+/// RGBA 颜色类. 灰色 (r = g = b, 如灰度模式) 在基因文件中只保存一个通道, 见 ColorRepr
 #[derive(Copy, Clone, Debug)]
 #[derive(Serialize, Deserialize)]
+#[serde(into = "ColorRepr", from = "ColorRepr")]
 struct Color {
     r: f32,
     g: f32,
     b: f32,
     a: f32,
 }
-impl Color {
-    /// 随机初始化
-    fn rand_new() -> Color {
-        Color {
-            r: random::uniform(0., 255.),
-            g: random::uniform(0., 255.),
-            b: random::uniform(0., 255.),
-            a: random::uniform(0., 1.),
+
+/// 颜色在基因文件中的表示: {"r": .., "g": .., "b": .., "a": ..}, 灰色时为 {"gray": .., "a": ..}
+#[derive(Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum ColorRepr {
+    Gray { gray: f32, a: f32 },
+    Rgb { r: f32, g: f32, b: f32, a: f32 },
+}
+impl From<Color> for ColorRepr {
+    fn from(Color { r, g, b, a }: Color) -> ColorRepr {
+        if r == g && g == b { ColorRepr::Gray { gray: r, a } } else { ColorRepr::Rgb { r, g, b, a } }
+    }
+}
+impl From<ColorRepr> for Color {
+    fn from(repr: ColorRepr) -> Color {
+        match repr {
+            ColorRepr::Gray { gray, a } => Color { r: gray, g: gray, b: gray, a },
+            ColorRepr::Rgb { r, g, b, a } => Color { r, g, b, a },
         }
     }
+}
+impl Color {
+    /// 在 model 的约束下随机初始化
+    fn rand_new(model: &ColorModel) -> Color {
+        let (r, g, b) = match model {
+            ColorModel::Free => (random::uniform(0., 255.), random::uniform(0., 255.), random::uniform(0., 255.)),
+            ColorModel::Palette(palette) => ColorModel::rand_palette_color(palette),
+            ColorModel::Gray => {
+                let v = random::uniform(0., 255.);
+                (v, v, v)
+            }
+            ColorModel::Ink(r, g, b) => (*r, *g, *b),
+        };
+        Color { r, g, b, a: random::uniform(0., 1.) }
+    }
 
-    /// 随机变异. amp 是缩放系数
-    fn mutate(&mut self, amp: f32) {
-        self.r = self.r.mutate(20. * amp, 0., 255.);
-        self.g = self.g.mutate(20. * amp, 0., 255.);
-        self.b = self.b.mutate(20. * amp, 0., 255.);
+    /// 在 model 的约束下随机变异. amp 是缩放系数
+    fn mutate(&mut self, amp: f32, model: &ColorModel) {
+        match model {
+            ColorModel::Free => {
+                self.r = self.r.mutate(20. * amp, 0., 255.);
+                self.g = self.g.mutate(20. * amp, 0., 255.);
+                self.b = self.b.mutate(20. * amp, 0., 255.);
+            }
+            ColorModel::Palette(palette) => {
+                // 以正比于 amp 的概率切换到调色板中的另一个颜色
+                if random::uniform(0., 1.) < 0.2 * amp {
+                    (self.r, self.g, self.b) = ColorModel::rand_palette_color(palette);
+                }
+            }
+            ColorModel::Gray => {
+                let v = self.r.mutate(20. * amp, 0., 255.);
+                (self.r, self.g, self.b) = (v, v, v);
+            }
+            ColorModel::Ink(..) => {}   // 只有透明度可以变
+        }
         self.a = self.a.mutate(0.03 * amp, 0., 1.);
     }
+
+    /// 在 model 的约束下将 RGB 设为 (r, g, b) 或与之最接近的合法颜色
+    fn set_rgb(&mut self, r: f32, g: f32, b: f32, model: &ColorModel) {
+        (self.r, self.g, self.b) = match model {
+            ColorModel::Free => (r.clamp(0., 255.), g.clamp(0., 255.), b.clamp(0., 255.)),
+            ColorModel::Palette(palette) => ColorModel::nearest_palette_color(palette, r, g, b),
+            ColorModel::Gray => {
+                let v = ((r + g + b) / 3.0).clamp(0., 255.);
+                (v, v, v)
+            }
+            ColorModel::Ink(r, g, b) => (*r, *g, *b),
+        };
+    }
+
+    /// 全部参数的可变引用及其量纲
+    fn params_mut(&mut self) -> [(&mut f32, f32); 4] {
+        [(&mut self.r, 255.), (&mut self.g, 255.), (&mut self.b, 255.), (&mut self.a, 1.)]
+    }
+
+    /// 将 RGB 换为 model 允许的颜色中最接近的一个, 越界的透明度规范到合法范围
+    fn project(&mut self, model: &ColorModel) {
+        self.set_rgb(self.r, self.g, self.b, model);
+        self.a = self.a.clamp(0., 1.);
+    }
 }
 
 
@@ -125,74 +277,288 @@ impl Line {
 }
 
 
-/// RGB 像素类
+/// RGBA 像素类. r, g, b 取值 [0, 255] (非预乘), a 取值 [0, 1]
 #[derive(Copy, Clone, Debug)]
 struct Pixel {
     r: f32,
     g: f32,
     b: f32,
+    a: f32,
 }
 impl Pixel {
-    /// 新建一个颜色为 r, g, b 的像素
-    fn new(r: f32, g: f32, b: f32) -> Pixel {
-        Pixel { r, g, b }
+    /// 新建一个颜色为 r, g, b, 不透明度为 a 的像素
+    fn new(r: f32, g: f32, b: f32, a: f32) -> Pixel {
+        Pixel { r, g, b, a }
     }
 
-    /// 层叠另一个半透明颜色
+    /// 层叠另一个半透明颜色 (Porter-Duff over)
     fn overlaid_by(&mut self, color: &Color) {
         let (my_weight, his_weight) = (1.0 - color.a, color.a);
+        if self.a >= 1.0 {
+            // 底色不透明时, 结果仍不透明, 直接按 alpha 混合
+            self.r = self.r * my_weight + color.r * his_weight;
+            self.g = self.g * my_weight + color.g * his_weight;
+            self.b = self.b * my_weight + color.b * his_weight;
+            return;
+        }
+        let out_a = his_weight + self.a * my_weight;
+        if out_a <= 0.0 { return }
+        let my_weight = self.a * my_weight / out_a;
+        let his_weight = his_weight / out_a;
         self.r = self.r * my_weight + color.r * his_weight;
         self.g = self.g * my_weight + color.g * his_weight;
         self.b = self.b * my_weight + color.b * his_weight;
+        self.a = out_a;
+    }
+
+    /// 亮度 (Rec. 601)
+    #[inline]
+    fn luminance(&self) -> f32 {
+        0.299 * self.r + 0.587 * self.g + 0.114 * self.b
+    }
+
+    /// 灰色像素 (r = g = b) 之间的 L2 距离, 只需比较一个通道
+    fn l2_dist_gray(p1: &Pixel, p2: &Pixel) -> f32 {
+        (p1.r - p2.r).powi(2)
+    }
+
+    /// 预乘 alpha 后灰色像素的 L2 距离, 再加上 alpha 通道 (放大到 [0, 255]) 的差异
+    fn l2_dist_gray_premultiplied(p1: &Pixel, p2: &Pixel) -> f32 {
+        (p1.r * p1.a - p2.r * p2.a).powi(2) + (255.0 * (p1.a - p2.a)).powi(2)
+    }
+
+    /// 亮度的 L2 距离
+    fn l2_dist_luminance(p1: &Pixel, p2: &Pixel) -> f32 {
+        (p1.luminance() - p2.luminance()).powi(2)
+    }
+
+    /// 预乘 alpha 后亮度的 L2 距离, 再加上 alpha 通道 (放大到 [0, 255]) 的差异
+    fn l2_dist_luminance_premultiplied(p1: &Pixel, p2: &Pixel) -> f32 {
+        (p1.luminance() * p1.a - p2.luminance() * p2.a).powi(2) + (255.0 * (p1.a - p2.a)).powi(2)
     }
 
     /// L2 距离
     fn l2_dist(p1: &Pixel, p2: &Pixel) -> f32 {
         (p1.r - p2.r).powi(2) + (p1.g - p2.g).powi(2) + (p1.b - p2.b).powi(2)
     }
+
+    /// 预乘 alpha 后的 L2 距离, 再加上 alpha 通道 (放大到 [0, 255]) 的差异
+    fn l2_dist_premultiplied(p1: &Pixel, p2: &Pixel) -> f32 {
+        (p1.r * p1.a - p2.r * p2.a).powi(2) +
+        (p1.g * p1.a - p2.g * p2.a).powi(2) +
+        (p1.b * p1.a - p2.b * p2.a).powi(2) +
+        (255.0 * (p1.a - p2.a)).powi(2)
+    }
+}
+
+
+/// 目标图片的透明区域在适应度中的处理方式
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlphaMode {
+    Ignore,     // 每个像素的误差按目标的不透明度加权, 全透明的区域不参与比较
+    Match,      // 透明度本身也要匹配 (按预乘 alpha 比较颜色, 并比较 alpha 通道), 宜配合透明背景使用
+}
+
+/// 作为目标图片时, 适应度比较哪些通道
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Channels {
+    Rgb,        // 三个颜色通道
+    Luminance,  // 只比较亮度 (单色模式: 目标已转为亮度, 个体是墨水色)
+    Gray,       // 目标和个体都是灰色 (灰度模式), 只需比较一个通道
+}
+
+/// 缩放图片时使用的重采样滤波器
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+impl Filter {
+    fn to_filter_type(self) -> image::imageops::FilterType {
+        match self {
+            Filter::Nearest => image::imageops::FilterType::Nearest,
+            Filter::Triangle => image::imageops::FilterType::Triangle,
+            Filter::CatmullRom => image::imageops::FilterType::CatmullRom,
+            Filter::Gaussian => image::imageops::FilterType::Gaussian,
+            Filter::Lanczos3 => image::imageops::FilterType::Lanczos3,
+        }
+    }
 }
 
-// 画布 (RGB 的)
+
+// 画布 (RGBA 的)
+#[derive(Clone)]
 pub struct Canvas {
     pub x_height: usize,
     pub y_width: usize,
     pixels: Vec<Pixel>,
+    pub alpha_mode: AlphaMode,  // 作为目标图片时, 透明区域在适应度中的处理方式
+    pub channels: Channels,     // 作为目标图片时, 适应度比较哪些通道
 }
 impl Canvas {
-    /// 新建一个 x_height × y_width 的纯黑画布
-    pub fn new(x_height: usize, y_width: usize, color: (f32, f32, f32)) -> Canvas {
+    /// 新建一个 x_height × y_width 的纯色画布, 不透明度为 alpha
+    pub fn new(x_height: usize, y_width: usize, color: (f32, f32, f32), alpha: f32) -> Canvas {
         let (r, g, b) = color;
         let n_pixels = x_height * y_width;
         let mut pixels = Vec::<Pixel>::with_capacity(n_pixels);
         for _ in 0..n_pixels {
-            pixels.push(Pixel::new(r, g, b));
+            pixels.push(Pixel::new(r, g, b, alpha));
         }
         Canvas {
             x_height,
             y_width,
             pixels,
+            alpha_mode: AlphaMode::Ignore,
+            channels: Channels::Rgb,
         }
     }
 
     /// 从图片中读取
     pub fn read_from_file(file_path: &str) -> Canvas {
-        let im = image::open(file_path).unwrap().to_rgb8();
+        Canvas::from_image(&image::open(file_path).unwrap_or_else(|e| panic!("无法读取图片 {}: {}", file_path, e)).to_rgba8())
+    }
+
+    /// 从图片中读取, 并进行预处理: 先裁剪出 crop = [left, top, width, height] 区域, 再缩放到长边不超过 max_dim.
+    /// 同时返回缩放前 (裁剪后) 的原始尺寸 (x_height, y_width), 供最终按原尺寸渲染
+    pub fn read_from_file_with(file_path: &str, crop: Option<[u32; 4]>, max_dim: Option<usize>, filter: Filter) -> (Canvas, (usize, usize)) {
+        let mut im = image::open(file_path).unwrap_or_else(|e| panic!("无法读取图片 {}: {}", file_path, e)).to_rgba8();
+        if let Some([left, top, width, height]) = crop {
+            let fits = left.checked_add(width).is_some_and(|right| right <= im.width())
+                && top.checked_add(height).is_some_and(|bottom| bottom <= im.height());
+            assert!(fits, "裁剪区域 {:?} 超出了图片范围 ({}×{})!", [left, top, width, height], im.width(), im.height());
+            im = image::imageops::crop_imm(&im, left, top, width, height).to_image();
+        }
+        let canvas = Canvas::from_image(&im);
+        let original_size = (canvas.x_height, canvas.y_width);
+        (canvas.shrunk(max_dim, filter), original_size)
+    }
+
+    /// 由 x_height × y_width 个像素的 RGBA 字节 (逐行排列, 非预乘) 构造. 长度不符时返回 None
+    pub fn from_rgba(x_height: usize, y_width: usize, rgba: Vec<u8>) -> Option<Canvas> {
+        image::RgbaImage::from_raw(y_width as u32, x_height as u32, rgba).map(|im| Canvas::from_image(&im))
+    }
+
+    /// 缩放到长边不超过 max_dim (已经不超过时原样返回)
+    pub fn shrunk(self, max_dim: Option<usize>, filter: Filter) -> Canvas {
+        let long_side = max(self.x_height, self.y_width);
+        match max_dim {
+            Some(max_dim) if long_side > max_dim => {
+                let ratio = max_dim as f32 / long_side as f32;
+                let x_height = ((self.x_height as f32 * ratio).round() as usize).max(1);
+                let y_width = ((self.y_width as f32 * ratio).round() as usize).max(1);
+                self.resized(x_height, y_width, filter)
+            }
+            _ => self,
+        }
+    }
+
+    /// 从 RGBA 图像构造
+    fn from_image(im: &image::RgbaImage) -> Canvas {
         let width = im.width() as usize;
         let height = im.height() as usize;
-        let rgb_seq = im.into_raw();
+        let rgba_seq = im.as_raw();
         let n_pixels = width * height;
         let mut pixels =  Vec::<Pixel>::with_capacity(n_pixels);
         for i in 0..n_pixels {
-            let r = rgb_seq[i * 3];
-            let g = rgb_seq[i * 3 + 1];
-            let b = rgb_seq[i * 3 + 2];
-            pixels.push(Pixel::new(r as f32, g as f32, b as f32));
+            let r = rgba_seq[i * 4];
+            let g = rgba_seq[i * 4 + 1];
+            let b = rgba_seq[i * 4 + 2];
+            let a = rgba_seq[i * 4 + 3];
+            pixels.push(Pixel::new(r as f32, g as f32, b as f32, a as f32 / 255.0));
         }
         Canvas {
             x_height: height,
             y_width: width,
             pixels,
+            alpha_mode: AlphaMode::Ignore,
+            channels: Channels::Rgb,
+        }
+    }
+
+    /// 转为 RGBA 图像
+    pub fn to_image(&self) -> image::RgbaImage {
+        let mut buffer = Vec::with_capacity(self.x_height * self.y_width * 4);
+        for pixel in &self.pixels {
+            buffer.push(pixel.r as u8);
+            buffer.push(pixel.g as u8);
+            buffer.push(pixel.b as u8);
+            buffer.push((pixel.a * 255.0).round() as u8);
+        }
+        image::RgbaImage::from_raw(self.y_width as u32, self.x_height as u32, buffer).unwrap()
+    }
+
+    /// 全部像素的平均颜色 (按不透明度加权)
+    pub fn mean_color(&self) -> (f32, f32, f32) {
+        let (mut r, mut g, mut b, mut w) = (0.0, 0.0, 0.0, 0.0);
+        for pixel in &self.pixels {
+            r += pixel.a * pixel.r;
+            g += pixel.a * pixel.g;
+            b += pixel.a * pixel.b;
+            w += pixel.a;
+        }
+        if w <= 0.0 { return (0., 0., 0.) }
+        (r / w, g / w, b / w)
+    }
+
+    /// 不透明像素各通道的中位数
+    pub fn median_color(&self) -> (f32, f32, f32) {
+        let opaque: Vec<&Pixel> = self.pixels.iter().filter(|pixel| pixel.a >= 0.5).collect();
+        if opaque.is_empty() { return (0., 0., 0.) }
+        let median = |channel: fn(&Pixel) -> f32| {
+            let mut values: Vec<f32> = opaque.iter().map(|pixel| channel(pixel)).collect();
+            let mid = values.len() / 2;
+            *values.select_nth_unstable_by(mid, |v1, v2| v1.partial_cmp(v2).unwrap()).1
+        };
+        (median(|pixel| pixel.r), median(|pixel| pixel.g), median(|pixel| pixel.b))
+    }
+
+    /// 不透明像素中出现最多的颜色: 每个通道量化为 16 级后统计直方图, 返回最多的一格内像素的平均颜色
+    pub fn dominant_color(&self) -> (f32, f32, f32) {
+        let bucket_of = |pixel: &Pixel| {
+            let q = |v: f32| (v.clamp(0., 255.) as usize) >> 4;
+            (q(pixel.r) << 8) | (q(pixel.g) << 4) | q(pixel.b)
+        };
+        let mut counts = vec![0usize; 16 * 16 * 16];
+        for pixel in self.pixels.iter().filter(|pixel| pixel.a >= 0.5) {
+            counts[bucket_of(pixel)] += 1;
+        }
+        let (best, &n) = counts.iter().enumerate().max_by_key(|(_, &n)| n).unwrap();
+        if n == 0 { return (0., 0., 0.) }
+        let (mut r, mut g, mut b) = (0.0, 0.0, 0.0);
+        for pixel in self.pixels.iter().filter(|pixel| pixel.a >= 0.5 && bucket_of(pixel) == best) {
+            r += pixel.r;
+            g += pixel.g;
+            b += pixel.b;
+        }
+        (r / n as f32, g / n as f32, b / n as f32)
+    }
+
+    /// 不透明像素的颜色, 至多均匀抽取 max_samples 个
+    pub fn opaque_colors(&self, max_samples: usize) -> Vec<(f32, f32, f32)> {
+        let opaque: Vec<&Pixel> = self.pixels.iter().filter(|pixel| pixel.a >= 0.5).collect();
+        let stride = max(1, opaque.len().div_ceil(max(max_samples, 1)));
+        opaque.iter().step_by(stride).map(|pixel| (pixel.r, pixel.g, pixel.b)).collect()
+    }
+
+    /// 是否含有 (半) 透明的像素
+    pub fn has_transparency(&self) -> bool {
+        self.pixels.iter().any(|pixel| pixel.a < 1.0)
+    }
+
+    /// 用 filter 缩放到 x_height × y_width, 返回新的画布
+    pub fn resized(&self, x_height: usize, y_width: usize, filter: Filter) -> Canvas {
+        if (x_height, y_width) == (self.x_height, self.y_width) {
+            return Canvas { pixels: self.pixels.clone(), ..*self };
         }
+        let im = image::imageops::resize(&self.to_image(), y_width as u32, x_height as u32, filter.to_filter_type());
+        Canvas { alpha_mode: self.alpha_mode, channels: self.channels, ..Canvas::from_image(&im) }
     }
 
     /// 二维 i, j 坐标转为线性索引
@@ -203,7 +569,7 @@ impl Canvas {
     }
 
     /// 画一条水平线
-    fn draw_horizontal_line(&mut self, i: usize, j1: usize, j2: usize, color: &Color) -> () {
+    fn draw_horizontal_line(&mut self, i: usize, j1: usize, j2: usize, color: &Color) {
         let j_left = min(j1, j2);
         let j_right = max(j1, j2);
         debug_assert!(j_right < self.y_width, "超出范围的网格坐标!");
@@ -213,37 +579,63 @@ impl Canvas {
         }
     }
 
-    /// 对比两个 Canvas 的逐像素 L2 差异
+    /// 对比两个 Canvas 的逐像素 L2 差异 (每通道的均方根).
+    /// canvas2 视为目标图片, 透明区域按其 alpha_mode 处理, 按其 channels 比较三个颜色通道或只比较亮度这一个通道
     pub fn l2_diff(canvas1: &Canvas, canvas2: &Canvas) -> f32 {
         debug_assert!(canvas1.x_height == canvas2.x_height && canvas1.y_width == canvas2.y_width, "只有尺寸相同的两个 canvas 才能计算差异!");
+        let (total_diff, total_weight) = match (canvas2.alpha_mode, canvas2.channels) {
+            (AlphaMode::Ignore, Channels::Rgb) => Canvas::sum_diff(canvas1, canvas2, true, Pixel::l2_dist),
+            (AlphaMode::Ignore, Channels::Luminance) => Canvas::sum_diff(canvas1, canvas2, true, Pixel::l2_dist_luminance),
+            (AlphaMode::Ignore, Channels::Gray) => Canvas::sum_diff(canvas1, canvas2, true, Pixel::l2_dist_gray),
+            (AlphaMode::Match, Channels::Rgb) => Canvas::sum_diff(canvas1, canvas2, false, Pixel::l2_dist_premultiplied),
+            (AlphaMode::Match, Channels::Luminance) => Canvas::sum_diff(canvas1, canvas2, false, Pixel::l2_dist_luminance_premultiplied),
+            (AlphaMode::Match, Channels::Gray) => Canvas::sum_diff(canvas1, canvas2, false, Pixel::l2_dist_gray_premultiplied),
+        };
+        if total_weight <= 0.0 { return 0.0 }
+        let n_channels = if canvas2.channels == Channels::Rgb { 3.0 } else { 1.0 };
+        f32::sqrt(total_diff / (total_weight * n_channels))
+    }
+
+    /// 逐像素累加 dist, 返回 (差异之和, 权重之和). weighted 时每个像素的权重为 canvas2 的不透明度, 否则为 1
+    #[inline]
+    fn sum_diff<F: Fn(&Pixel, &Pixel) -> f32>(canvas1: &Canvas, canvas2: &Canvas, weighted: bool, dist: F) -> (f32, f32) {
         let mut total_diff = 0.0;
-        for idx in 0..canvas1.pixels.len() {
-            total_diff += Pixel::l2_dist(&canvas1.pixels[idx], &canvas2.pixels[idx]);
+        let mut total_weight = 0.0;
+        for (p1, p2) in canvas1.pixels.iter().zip(&canvas2.pixels) {
+            let weight = if weighted { p2.a } else { 1.0 };
+            total_diff += weight * dist(p1, p2);
+            total_weight += weight;
         }
-        f32::sqrt(total_diff / (canvas1.pixels.len() as f32 * 3.0))
+        (total_diff, total_weight)
+    }
+
+    /// 将每个像素转为其亮度对应的灰色, 并令其作为目标图片时只比较亮度 (个体也都是灰色时可设 channels 为 Gray)
+    pub fn convert_to_luminance(&mut self) {
+        for pixel in &mut self.pixels {
+            let y = pixel.luminance();
+            (pixel.r, pixel.g, pixel.b) = (y, y, y);
+        }
+        self.channels = Channels::Luminance;
     }
 
     /// 将自己以 ASCII 格式输出
+    #[allow(dead_code)]
     pub fn print_as_ascii(&mut self, buf: &mut String) {
-        write!(buf, "h={} w={}\n", self.x_height, self.y_width).unwrap();
+        writeln!(buf, "h={} w={}", self.x_height, self.y_width).unwrap();
         for pixel in &self.pixels {
-            write!(buf, "{} {} {}\n", pixel.r, pixel.g, pixel.b).unwrap();
+            writeln!(buf, "{} {} {}", pixel.r, pixel.g, pixel.b).unwrap();
         }
     }
 
-    /// 将自己以图片格式输出到文件
-    pub fn write_to_file(&self, filename: &str) {
-        let mut buffer = Vec::with_capacity(self.x_height * self.y_width * 3);
-        for pixel in &self.pixels {
-            buffer.push(pixel.r as u8);
-            buffer.push(pixel.g as u8);
-            buffer.push(pixel.b as u8);
+    /// 将自己以图片格式输出到文件. 按照是否含有透明像素、是否为灰度模式 (grayscale), 分别输出 RGBA, RGB, 灰度 + Alpha, 灰度
+    pub fn write_to_file(&self, filename: &str, grayscale: bool) {
+        let im = image::DynamicImage::ImageRgba8(self.to_image());
+        match (self.has_transparency(), grayscale) {
+            (true, false) => im.save(filename).unwrap(),
+            (false, false) => im.to_rgb8().save(filename).unwrap(),
+            (true, true) => im.to_luma_alpha8().save(filename).unwrap(),
+            (false, true) => im.to_luma8().save(filename).unwrap(),
         }
-        image::save_buffer(filename,
-                           &buffer,
-                           self.y_width as u32,
-                           self.x_height as u32,
-                           image::ColorType::Rgb8).unwrap();
     }
 
 }
@@ -252,7 +644,7 @@ impl Canvas {
 
 /// 三种用于生成图片的基本图元
 #[derive(Copy, Clone, Serialize, Deserialize)]
-#[serde(tag = "type", content = "data")]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
 enum Shape {
     Triangle {
         p1: Point2D,
@@ -262,7 +654,7 @@ enum Shape {
     },
     Circle {
         center: Point2D,
-        radius: [f32; 1],
+        radius: f32,
         color: Color,
     },
     Rectangle {
@@ -272,24 +664,24 @@ enum Shape {
     },
 }
 impl Shape {
-    /// 根据指定名称, 随机初始化一个形状
-    fn rand_new(type_name: &str, x_height: usize, y_width: usize) -> Shape {
+    /// 根据指定名称, 随机初始化一个形状. 颜色受 colors 约束
+    fn rand_new(type_name: &str, x_height: usize, y_width: usize, colors: &ColorModel) -> Shape {
         match type_name.to_lowercase().as_str() {
             "triangle" => Shape::Triangle {
                 p1: Point2D::rand_new(0.0, x_height as f32, 0.0, y_width as f32),
                 p2: Point2D::rand_new(0.0, x_height as f32, 0.0, y_width as f32),
                 p3: Point2D::rand_new(0.0, x_height as f32, 0.0, y_width as f32),
-                color: Color::rand_new(),
+                color: Color::rand_new(colors),
             },
             "circle" => Shape::Circle {
                 center: Point2D::rand_new(0.0, x_height as f32, 0.0, y_width as f32),
-                radius: [crate::random::uniform(0.0, 0.1 * min(x_height, y_width) as f32).clamp(1.0, f32::INFINITY)],  // FIXME: MAGIC_NUMBER: 0.1
-                color: Color::rand_new(),
+                radius: random::uniform(0.0, 0.1 * min(x_height, y_width) as f32).clamp(1.0, f32::INFINITY),  // FIXME: MAGIC_NUMBER: 0.1
+                color: Color::rand_new(colors),
             },
             "rectangle" => Shape::Rectangle {
                 p1: Point2D::rand_new(0.0, x_height as f32, 0.0, y_width as f32),
                 p2: Point2D::rand_new(0.0, x_height as f32, 0.0, y_width as f32),
-                color: Color::rand_new(),
+                color: Color::rand_new(colors),
             },
             _ => panic!("未知的 Shape 类别!"),
         }
@@ -298,40 +690,198 @@ impl Shape {
     /// 对当前形状进行变异
     ///  - `canvas_size`: 当前画布的短边长度
     ///  - `amp`: 变异的增益系数, 指定为 1.0 为默认
-    fn mutate(&mut self, canvas_size: usize, amp: f32) {
+    ///  - `colors`: 颜色的取值约束
+    fn mutate(&mut self, canvas_size: usize, amp: f32, colors: &ColorModel) {
         let pixel_sigma = (canvas_size as f32) * 0.03;    // 位置参数的变动, 单位为 pixel, 含义是正态分布的 1σ
         match self {
             Shape::Triangle { p1, p2, p3, color } => {
                 p1.mutate(pixel_sigma, amp);
                 p2.mutate(pixel_sigma, amp);
                 p3.mutate(pixel_sigma, amp);
-                color.mutate(amp);
+                color.mutate(amp, colors);
             }
             Shape::Circle { center, radius, color } => {
                 center.mutate(pixel_sigma, amp);
-                color.mutate(amp);
-                radius[0] = radius[0].mutate(pixel_sigma, f32::NEG_INFINITY, f32::INFINITY);
+                color.mutate(amp, colors);
+                *radius = radius.mutate(pixel_sigma * amp, f32::NEG_INFINITY, f32::INFINITY);
             },
             Shape::Rectangle { p1, p2, color } => {
                 p1.mutate(pixel_sigma, amp);
                 p2.mutate(pixel_sigma, amp);
-                color.mutate(amp);
+                color.mutate(amp, colors);
             }
-            _ => panic!(),
         }
     }
 
+    /// 将坐标沿 x 方向缩放 sx 倍, 沿 y 方向缩放 sy 倍. 半径按两者的平均缩放
+    fn scale(&mut self, sx: f32, sy: f32) {
+        let scale_point = |p: &mut Point2D| { p.x *= sx; p.y *= sy; };
+        match self {
+            Shape::Triangle { p1, p2, p3, .. } => {
+                scale_point(p1);
+                scale_point(p2);
+                scale_point(p3);
+            }
+            Shape::Circle { center, radius, .. } => {
+                scale_point(center);
+                *radius *= (sx + sy) / 2.0;
+            }
+            Shape::Rectangle { p1, p2, .. } => {
+                scale_point(p1);
+                scale_point(p2);
+            }
+        }
+    }
+
+    /// 全部连续参数 (坐标、半径、颜色) 的可变引用及其量纲. 坐标和半径的量纲为画布短边长度 canvas_size
+    fn params_mut(&mut self, canvas_size: f32) -> Vec<(&mut f32, f32)> {
+        let mut params = Vec::with_capacity(10);
+        let color = match self {
+            Shape::Triangle { p1, p2, p3, color } => {
+                for p in [p1, p2, p3] {
+                    params.push((&mut p.x, canvas_size));
+                    params.push((&mut p.y, canvas_size));
+                }
+                color
+            }
+            Shape::Circle { center, radius, color } => {
+                params.push((&mut center.x, canvas_size));
+                params.push((&mut center.y, canvas_size));
+                params.push((radius, canvas_size));
+                color
+            }
+            Shape::Rectangle { p1, p2, color } => {
+                for p in [p1, p2] {
+                    params.push((&mut p.x, canvas_size));
+                    params.push((&mut p.y, canvas_size));
+                }
+                color
+            }
+        };
+        params.extend(color.params_mut());
+        params
+    }
+
+    /// 与另一个图形的差异, 取值 [0, 1]. 类型不同时为 1, 否则为归一化参数差的均方根 (截断到 1)
+    fn distance(&self, other: &Shape, canvas_size: f32) -> f32 {
+        if std::mem::discriminant(self) != std::mem::discriminant(other) { return 1.0 }
+        let (mut me, mut him) = (*self, *other);
+        let (my_params, his_params) = (me.params_mut(canvas_size), him.params_mut(canvas_size));
+        let sum_sq: f32 = my_params.iter().zip(&his_params)
+            .map(|((p1, scale), (p2, _))| ((**p1 - **p2) / scale).powi(2))
+            .sum();
+        f32::sqrt(sum_sq / my_params.len() as f32).min(1.0)
+    }
+
+    /// 转为 SVG 元素. 注意 SVG 的横坐标对应这里的 y, 纵坐标对应这里的 x
+    fn svg_element(&self) -> String {
+        let color = self.color();
+        let fill = format!(r#"fill="rgb({},{},{})" fill-opacity="{}""#, color.r.round(), color.g.round(), color.b.round(), color.a);
+        match self {
+            Shape::Triangle { p1, p2, p3, .. } => {
+                format!(r#"<polygon points="{},{} {},{} {},{}" {}/>"#, p1.y, p1.x, p2.y, p2.x, p3.y, p3.x, fill)
+            }
+            Shape::Circle { center, radius, .. } => {
+                format!(r#"<circle cx="{}" cy="{}" r="{}" {}/>"#, center.y, center.x, radius.max(0.0), fill)
+            }
+            Shape::Rectangle { p1, p2, .. } => {
+                format!(r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+                        p1.y.min(p2.y), p1.x.min(p2.x), (p1.y - p2.y).abs(), (p1.x - p2.x).abs(), fill)
+            }
+        }
+    }
+
+    /// 自己的颜色
+    fn color(&self) -> &Color {
+        match self {
+            Shape::Triangle { color, .. } | Shape::Circle { color, .. } | Shape::Rectangle { color, .. } => color,
+        }
+    }
+
+    /// 自己的颜色 (可变引用)
+    fn color_mut(&mut self) -> &mut Color {
+        match self {
+            Shape::Triangle { color, .. } | Shape::Circle { color, .. } | Shape::Rectangle { color, .. } => color,
+        }
+    }
+
+    /// 类别名称, 与 rand_new 接受的名称一致
+    fn kind(&self) -> &'static str {
+        match self {
+            Shape::Triangle { .. } => "triangle",
+            Shape::Circle { .. } => "circle",
+            Shape::Rectangle { .. } => "rectangle",
+        }
+    }
+
+    /// 几何面积 (单位为像素²). 半径为负的圆面积为 0
+    fn area(&self) -> f32 {
+        match self {
+            Shape::Triangle { p1, p2, p3, .. } => {
+                0.5 * ((p2.x - p1.x) * (p3.y - p1.y) - (p3.x - p1.x) * (p2.y - p1.y)).abs()
+            }
+            Shape::Circle { radius, .. } => std::f32::consts::PI * radius.max(0.0).powi(2),
+            Shape::Rectangle { p1, p2, .. } => (p1.x - p2.x).abs() * (p1.y - p2.y).abs(),
+        }
+    }
+
+    /// 外接矩形 (x_min, x_max, y_min, y_max)
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        let points: Vec<Point2D> = match self {
+            Shape::Triangle { p1, p2, p3, .. } => vec![*p1, *p2, *p3],
+            Shape::Circle { center, radius, .. } => {
+                let r = radius.abs();
+                vec![Point2D::new(center.x - r, center.y - r), Point2D::new(center.x + r, center.y + r)]
+            }
+            Shape::Rectangle { p1, p2, .. } => vec![*p1, *p2],
+        };
+        points.iter().fold((f32::INFINITY, f32::NEG_INFINITY, f32::INFINITY, f32::NEG_INFINITY), |(x0, x1, y0, y1), p| {
+            (x0.min(p.x), x1.max(p.x), y0.min(p.y), y1.max(p.y))
+        })
+    }
+
+    /// 在 x_height × y_width 的画布上的退化之处
+    fn defects(&self, x_height: usize, y_width: usize) -> Vec<&'static str> {
+        const MIN_AREA: f32 = 0.5;  // 面积小于半个像素视为退化
+        let mut defects = Vec::new();
+        let mut me = *self;
+        if me.params_mut(1.0).iter().any(|(p, _)| !p.is_finite()) {
+            defects.push("参数不是有限值");
+            return defects;
+        }
+        if let Shape::Circle { radius, .. } = self {
+            if *radius < 0.0 { defects.push("半径为负") }
+        }
+        if self.area() < MIN_AREA {
+            defects.push("面积几乎为零");
+        }
+        // 像素中心位于 0..=x_height-1, 0..=y_width-1; 完全在其外的图形渲染时会被压到边界上
+        let (x_min, x_max, y_min, y_max) = self.bounds();
+        if x_max < -0.5 || x_min > x_height as f32 - 0.5 || y_max < -0.5 || y_min > y_width as f32 - 0.5 {
+            defects.push("完全在画布之外");
+        }
+        defects
+    }
+
     /// 把自己绘制在目标画布上
     fn draw_to(&self, canvas: &mut Canvas) {
+        let color = *self.color();
+        self.for_each_span(canvas.x_height, canvas.y_width, |i, j_left, j_right| {
+            canvas.draw_horizontal_line(i, j_left, j_right, &color);
+        });
+    }
+
+    /// 按行扫描自己在 x_height × y_width 画布上覆盖的区域, 对每一行调用 f(i, j_left, j_right)
+    fn for_each_span<F: FnMut(usize, usize, usize)>(&self, x_height: usize, y_width: usize, mut f: F) {
 
         // 将浮点坐标对齐到网格, 并对越界的规范到边界
-        let x_max = (canvas.x_height - 1) as f32;
-        let y_max = (canvas.y_width - 1) as f32;
-        let x2i = |x: f32| {x.round().clamp(0.0, x_max as f32) as usize};
-        let y2j = |y: f32| {y.round().clamp(0.0, y_max as f32) as usize};
+        let x_max = (x_height - 1) as f32;
+        let y_max = (y_width - 1) as f32;
+        let x2i = |x: f32| {x.round().clamp(0.0, x_max) as usize};
+        let y2j = |y: f32| {y.round().clamp(0.0, y_max) as usize};
 
         match self {
-            Shape::Triangle {p1, p2, p3, color} => {
+            Shape::Triangle {p1, p2, p3, ..} => {
                 // 首先对三个点重命名, 使得 A.x ≤ B.x ≤ C.x
                 let mut three_points = [p1, p2, p3];
                 three_points.sort_by(|p1, p2| p1.x.partial_cmp(&p2.x).unwrap());
@@ -355,18 +905,18 @@ impl Shape {
                 for i in i_start..i_mid {
                     let j_one_side = y2j(l_ab.at(i as f32));
                     let j_another_side = y2j(l_ac.at(i as f32));
-                    canvas.draw_horizontal_line(i, j_one_side, j_another_side, color);
+                    f(i, min(j_one_side, j_another_side), max(j_one_side, j_another_side));
                 }
                 // Part II
                 for i in i_mid..=i_end {
                     let j_one_side = y2j(l_bc.at(i as f32));
                     let j_another_side = y2j(l_ac.at(i as f32));
-                    canvas.draw_horizontal_line(i, j_one_side, j_another_side, color);
+                    f(i, min(j_one_side, j_another_side), max(j_one_side, j_another_side));
                 }
             },
 
-            Shape::Circle { center, radius, color } => {
-                let (r, cx, cy) = (radius[0], center.x, center.y);
+            Shape::Circle { center, radius, .. } => {
+                let (r, cx, cy) = (*radius, center.x, center.y);
                 let i_start = x2i(center.x - r);
                 let i_end = x2i(center.x + r);
                 //
@@ -380,11 +930,11 @@ impl Shape {
                 let j_right = |i| { y2j(cy + f32::sqrt(r.powi(2) - (i as f32 - cx).powi(2) )) };
                 // 按行绘制
                 for i in i_start..=i_end {
-                    canvas.draw_horizontal_line(i, j_left(i), j_right(i), color);
+                    f(i, min(j_left(i), j_right(i)), max(j_left(i), j_right(i)));
                 }
             },
 
-            Shape::Rectangle {p1, p2, color} => {
+            Shape::Rectangle {p1, p2, ..} => {
                 // 找到这个矩形的四个边界
                 let x_min = f32::min(p1.x, p2.x);
                 let x_max = f32::max(p1.x, p2.x);
@@ -403,7 +953,7 @@ impl Shape {
                 let j_right = y2j(y_max);
                 // 按行绘制
                 for i in i_start..=i_end {
-                    canvas.draw_horizontal_line(i, j_left, j_right, color);
+                    f(i, j_left, j_right);
                 }
             }
         }
@@ -411,15 +961,84 @@ impl Shape {
 }
 
 
-/// 个体类. 每个个体就是一些 Shape 的有序列表, 越靠后的图元渲染在越上层
-#[derive(Serialize, Deserialize)]
+/// 单个图形的概况 (供 inspect 子命令使用)
+#[derive(Clone, Debug, Serialize)]
+pub struct ShapeSummary {
+    pub kind: &'static str,             // triangle, circle, rectangle
+    pub area: f32,                      // 几何面积 (像素²)
+    pub alpha: f32,                     // 不透明度
+    pub defects: Vec<&'static str>,     // 退化之处: 参数不是有限值、半径为负、面积几乎为零、完全在画布之外
+}
+
+
+/// 个体类. 每个个体就是一些 Shape 的有序列表, 越靠后的图元渲染在越上层.
+/// 序列化为当前版本的基因文件格式, 反序列化时接受任意旧版本 (见 genome.rs)
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "Genome", try_from = "serde_json::Value")]
 pub struct Individual {
     shapes: Vec<Shape>,
     fitness: Option<f32>,
     env_height: usize,
     env_width: usize,
     bg_color: (f32, f32, f32),
+    bg_alpha: f32,
+}
+
+
+/// 当前版本的基因文件, 见 genome.rs
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Genome {
+    version: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<GenomeMetadata>,
+    height: usize,
+    width: usize,
+    bg_color: (f32, f32, f32),
+    bg_alpha: f32,
+    shapes: Vec<Shape>,
 }
+impl Genome {
+    fn new(ind: Individual, metadata: Option<GenomeMetadata>) -> Genome {
+        Genome {
+            version: genome::GENOME_VERSION,
+            metadata,
+            height: ind.env_height,
+            width: ind.env_width,
+            bg_color: ind.bg_color,
+            bg_alpha: ind.bg_alpha,
+            shapes: ind.shapes,
+        }
+    }
+
+    /// 由任意版本的基因文件 (已解析为 JSON) 读取
+    fn from_value(value: serde_json::Value) -> Result<Genome, String> {
+        serde_json::from_value(genome::migrate(value)?).map_err(|e| format!("基因文件有误: {}", e))
+    }
+
+    fn into_individual(self) -> Individual {
+        Individual {
+            shapes: self.shapes,
+            fitness: None,
+            env_height: self.height,
+            env_width: self.width,
+            bg_color: self.bg_color,
+            bg_alpha: self.bg_alpha,
+        }
+    }
+}
+impl From<Individual> for Genome {
+    fn from(ind: Individual) -> Genome {
+        Genome::new(ind, None)
+    }
+}
+impl TryFrom<serde_json::Value> for Individual {
+    type Error = String;
+    fn try_from(value: serde_json::Value) -> Result<Individual, String> {
+        Genome::from_value(value).map(Genome::into_individual)
+    }
+}
+
 impl Individual {
     /// 初始化一个空白个体
     pub fn new(env_height: usize, env_width: usize, bg_color: (f32, f32, f32)) -> Individual {
@@ -429,26 +1048,66 @@ impl Individual {
             env_height,
             env_width,
             bg_color,
+            bg_alpha: 1.0,
         }
     }
 
-    /// 复制一份自己
-    pub fn clone(&self) -> Individual {
-        let shapes = self.shapes.clone();
+    /// 设置背景的不透明度. 0 为透明背景
+    pub fn set_bg_alpha(&mut self, bg_alpha: f32) {
+        self.bg_alpha = bg_alpha;
+        self.fitness = None;    // fitness 有待重新计算
+    }
+
+    /// 将自己缩放到 env_height × env_width 的画布上, 返回新的个体
+    pub fn rescaled(&self, env_height: usize, env_width: usize) -> Individual {
+        let sx = env_height as f32 / self.env_height as f32;
+        let sy = env_width as f32 / self.env_width as f32;
+        let mut shapes = self.shapes.clone();
+        for shape in &mut shapes {
+            shape.scale(sx, sy);
+        }
         Individual {
-            shapes, ..*self
+            shapes,
+            fitness: None,
+            env_height,
+            env_width,
+            bg_color: self.bg_color,
+            bg_alpha: self.bg_alpha,
         }
     }
 
-    /// 从 JSON 字符串中读取一个个体
+    /// 从 (任意版本的) 基因文件内容中读取一个个体
     pub fn from_json(json: &str) -> Individual {
-        serde_json::from_str(json).unwrap()
+        Individual::from_json_with_metadata(json).0
+    }
+
+    /// 从 (任意版本的) 基因文件内容中读取一个个体, 以及其中记录的元数据
+    pub fn from_json_with_metadata(json: &str) -> (Individual, Option<GenomeMetadata>) {
+        let value = serde_json::from_str(json).unwrap_or_else(|e| panic!("基因文件不是合法的 JSON: {}", e));
+        let mut genome = Genome::from_value(value).unwrap_or_else(|message| panic!("{}", message));
+        let metadata = genome.metadata.take();
+        (genome.into_individual(), metadata)
+    }
+
+    /// 转为当前版本的基因文件内容
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// 转为当前版本的基因文件内容, 并记录元数据
+    pub fn to_json_with_metadata(&self, metadata: &GenomeMetadata) -> String {
+        serde_json::to_string(&Genome::new(self.clone(), Some(metadata.clone()))).unwrap()
     }
 
     /// 将自身属性以 JSON 格式输出
+    #[allow(dead_code)]
     pub fn print_as_json(&self) {
-        let json = serde_json::to_string(self).unwrap();
-        println!("{}", json);
+        println!("{}", self.to_json());
+    }
+
+    /// 个体所在画布的尺寸 (env_height, env_width)
+    pub fn env_size(&self) -> (usize, usize) {
+        (self.env_height, self.env_width)
     }
 
     /// 个体包含的 Shape 数目
@@ -456,28 +1115,257 @@ impl Individual {
         self.shapes.len()
     }
 
-    /// 令个体第 which 个 Shape 进行变异
-    pub fn mutate_shape(&mut self, which: usize, canvas_size: usize, amp: f32) {
+    /// 将全部图形的连续参数展平为一个向量, 同时返回每个参数的量纲 (图形数目和顺序不在其中)
+    pub fn params(&self) -> (Vec<f32>, Vec<f32>) {
+        let canvas_size = min(self.env_height, self.env_width) as f32;
+        let mut shapes = self.shapes.clone();
+        shapes.iter_mut()
+            .flat_map(|shape| shape.params_mut(canvas_size))
+            .map(|(param, scale)| (*param, scale))
+            .unzip()
+    }
+
+    /// 与另一个个体的基因差异, 取值 [0, 1].
+    /// 按下标逐个比较图形 (见 Shape::distance), 多出来的图形每个计差异 1, 最后对图形数目取平均
+    pub fn distance(&self, other: &Individual) -> f32 {
+        let n_max = max(self.n_shapes(), other.n_shapes());
+        if n_max == 0 { return 0.0 }
+        let canvas_size = min(self.env_height, self.env_width) as f32;
+        let n_common = min(self.n_shapes(), other.n_shapes());
+        let common: f32 = self.shapes.iter().zip(&other.shapes)
+            .map(|(s1, s2)| s1.distance(s2, canvas_size))
+            .sum();
+        (common + (n_max - n_common) as f32) / n_max as f32
+    }
+
+    /// 按 params() 的顺序写回全部连续参数, 颜色随后投影到 colors 允许的范围内 (越界的规范到合法范围)
+    pub fn set_params(&mut self, params: &[f32], colors: &ColorModel) {
+        let canvas_size = min(self.env_height, self.env_width) as f32;
+        let mut n_set = 0;
+        for shape in &mut self.shapes {
+            for (slot, value) in shape.params_mut(canvas_size).into_iter().zip(&params[n_set..]) {
+                *slot.0 = *value;
+                n_set += 1;
+            }
+            shape.color_mut().project(colors);
+        }
+        debug_assert!(n_set == params.len(), "参数个数与图形不匹配!");
+        self.fitness = None;    // fitness 有待重新计算
+    }
+
+    /// 全部图形用到的不同颜色 (RGB)
+    pub fn distinct_colors(&self) -> Vec<(f32, f32, f32)> {
+        let mut colors: Vec<(f32, f32, f32)> = Vec::new();
+        for shape in &self.shapes {
+            let Color { r, g, b, .. } = *shape.color();
+            if !colors.contains(&(r, g, b)) {
+                colors.push((r, g, b));
+            }
+        }
+        colors
+    }
+
+    /// 令个体的背景色进行变异. 灰度模式下背景色保持为灰色
+    pub fn mutate_bg(&mut self, amp: f32, colors: &ColorModel) {
+        let (r, g, b) = self.bg_color;
+        self.bg_color = match colors {
+            ColorModel::Gray => {
+                let v = r.mutate(20. * amp, 0., 255.);
+                (v, v, v)
+            }
+            _ => (r.mutate(20. * amp, 0., 255.), g.mutate(20. * amp, 0., 255.), b.mutate(20. * amp, 0., 255.)),
+        };
+        self.fitness = None;    // fitness 有待重新计算
+    }
+
+    /// 令个体第 which 个 Shape 进行变异, 颜色受 colors 约束
+    pub fn mutate_shape(&mut self, which: usize, canvas_size: usize, amp: f32, colors: &ColorModel) {
         debug_assert!(which < self.n_shapes(), "越界的下标!");
-        self.shapes[which].mutate(canvas_size, amp);
+        self.shapes[which].mutate(canvas_size, amp, colors);
         self.fitness = None;    // fitness 有待重新计算
     }
 
-    /// 令个体添加一个 Shape. 类型指定, 但属性随机.
-    pub fn add_shape(&mut self, type_name: &str) {
-        self.shapes.push(Shape::rand_new(type_name, self.env_height, self.env_width));
+    /// 固定第 which 个 Shape 的几何形状和 alpha, 将其 RGB 设为覆盖区域上的最小二乘最优解 (误差按 target 的 alpha_mode 计算).
+    /// 底色取它下方各层叠加的结果 (忽略上方图形的遮挡), 因此对最顶层的图形是精确解. 下方各层只在覆盖区域内绘制.
+    /// 受 colors 约束时取最接近最优解的合法颜色 (误差是颜色的二次函数, 因此这也是约束下的最优解)
+    pub fn solve_color(&mut self, which: usize, target: &Canvas, colors: &ColorModel) {
+        debug_assert!(which < self.n_shapes(), "越界的下标!");
+        let a = self.shapes[which].color().a;
+        if a < 1e-3 { return }     // 几乎全透明时颜色无关紧要, 且会导致除零
+        // 它覆盖的区域: 每行的 (j_left, j_right, 该行在 below 中的起始下标)
+        let (x_height, y_width) = (self.env_height, self.env_width);
+        let mut rows: Vec<Option<(usize, usize, usize)>> = vec![None; x_height];
+        let mut n_covered = 0;
+        self.shapes[which].for_each_span(x_height, y_width, |i, j_left, j_right| {
+            rows[i] = Some((j_left, j_right, n_covered));
+            n_covered += j_right - j_left + 1;
+        });
+        // 在覆盖区域内绘制它下方的部分
+        let (r, g, b) = self.bg_color;
+        let mut below = vec![Pixel::new(r, g, b, self.bg_alpha); n_covered];
+        for shape in &self.shapes[..which] {
+            let color = *shape.color();
+            shape.for_each_span(x_height, y_width, |i, j_left, j_right| {
+                if let Some((row_left, row_right, offset)) = rows[i] {
+                    for j in max(j_left, row_left)..=min(j_right, row_right) {
+                        below[offset + j - row_left].overlaid_by(&color);
+                    }
+                }
+            });
+        }
+        // 底色 B 的不透明度为 β 时, 叠加颜色 C 后 (Porter-Duff over) 不透明度为 α = a + β·(1-a), 与 C 无关,
+        // 非预乘颜色为 (β·(1-a)·B + a·C) / α, 预乘颜色为 β·(1-a)·B + a·C.
+        // 两种 alpha_mode 下每个像素的误差都形如 w·|k·C - y|², 因此 C = Σ w·k·y / Σ w·k²
+        let (mut sum_r, mut sum_g, mut sum_b, mut sum_w) = (0.0, 0.0, 0.0, 0.0);
+        for (i, row) in rows.into_iter().enumerate() {
+            let Some((j_left, j_right, offset)) = row else { continue };
+            for j in j_left..=j_right {
+                let (t, b) = (&target.pixels[target.idx_of(i, j)], &below[offset + j - j_left]);
+                let b_weight = b.a * (1.0 - a);
+                let (w, k, t_scale, b_scale) = match target.alpha_mode {
+                    // 比较非预乘颜色, 按目标的不透明度加权
+                    AlphaMode::Ignore => { let out_a = a + b_weight; (t.a, a / out_a, 1.0, b_weight / out_a) }
+                    // 比较预乘颜色, alpha 通道的误差与 C 无关
+                    AlphaMode::Match => (1.0, a, t.a, b_weight),
+                };
+                sum_r += w * k * (t_scale * t.r - b_scale * b.r);
+                sum_g += w * k * (t_scale * t.g - b_scale * b.g);
+                sum_b += w * k * (t_scale * t.b - b_scale * b.b);
+                sum_w += w * k * k;
+            }
+        }
+        if sum_w <= 0.0 { return }
+        let color = self.shapes[which].color_mut();
+        color.set_rgb(sum_r / sum_w, sum_g / sum_w, sum_b / sum_w, colors);
         self.fitness = None;    // fitness 有待重新计算
     }
 
+    /// 令个体添加一个 Shape. 类型指定, 但属性随机, 颜色受 colors 约束.
+    pub fn add_shape(&mut self, type_name: &str, colors: &ColorModel) {
+        self.shapes.push(Shape::rand_new(type_name, self.env_height, self.env_width, colors));
+        self.fitness = None;    // fitness 有待重新计算
+    }
+
+    /// 令个体移除第 which 个 Shape
+    pub fn remove_shape(&mut self, which: usize) {
+        debug_assert!(which < self.n_shapes(), "越界的下标!");
+        self.shapes.remove(which);
+        self.fitness = None;    // fitness 有待重新计算
+    }
+
+    /// 从上到下逐个尝试移除图形, 移除后适应度不变差的就真正移除. 返回移除的图形数目
+    pub fn prune(&mut self, target: &Canvas) -> usize {
+        self.calc_fitness(target);
+        let n_before = self.n_shapes();
+        for which in (0..n_before).rev() {
+            let mut trial = self.clone();
+            trial.remove_shape(which);
+            trial.calc_fitness(target);
+            if trial.get_fitness() <= self.get_fitness() {
+                *self = trial;
+            }
+        }
+        n_before - self.n_shapes()
+    }
+
+    /// 按 z 序 (从下到上) 列出每个图形的概况
+    pub fn shape_summaries(&self) -> Vec<ShapeSummary> {
+        self.shapes.iter().map(|shape| ShapeSummary {
+            kind: shape.kind(),
+            area: shape.area(),
+            alpha: shape.color().a,
+            defects: shape.defects(self.env_height, self.env_width),
+        }).collect()
+    }
+
+    /// 每个像素被多少个图形覆盖 (按行排列, 共 env_height × env_width 个)
+    pub fn coverage(&self) -> Vec<u32> {
+        let mut counts = vec![0; self.env_height * self.env_width];
+        for shape in &self.shapes {
+            shape.for_each_span(self.env_height, self.env_width, |i, j_left, j_right| {
+                for count in &mut counts[i * self.env_width + j_left..=i * self.env_width + j_right] {
+                    *count += 1;
+                }
+            });
+        }
+        counts
+    }
+
     /// 绘制自身到 Canvas 并返回这个 Canvas
     pub fn draw_self(&self) -> Canvas {
-        let mut canvas = Canvas::new(self.env_height, self.env_width, self.bg_color);
+        let mut canvas = Canvas::new(self.env_height, self.env_width, self.bg_color, self.bg_alpha);
         for shape in &self.shapes {
             shape.draw_to(&mut canvas);
         }
         canvas
     }
 
+    /// 将自身以 SVG 格式输出 (坐标系与 Canvas 一致: x 向下, y 向右)
+    pub fn to_svg(&self) -> String {
+        let elements: Vec<String> = self.shapes.iter().map(|shape| shape.svg_element()).collect();
+        self.wrap_svg(&elements)
+    }
+
+    /// 将自身输出为逐个出现图形的 SVG 动画 (SMIL): 第 i 个图形在 i × interval 秒时出现, 用 fade 秒淡入 (为 0 时直接出现)
+    pub fn to_animated_svg(&self, interval: f32, fade: f32) -> String {
+        let elements: Vec<String> = self.shapes.iter().enumerate().map(|(i, shape)| {
+            let begin = i as f32 * interval;
+            let anim = if fade > 0.0 {
+                format!(r#"<animate attributeName="opacity" from="0" to="1" begin="{}s" dur="{}s" fill="freeze"/>"#, begin, fade)
+            } else {
+                format!(r#"<set attributeName="opacity" to="1" begin="{}s" fill="freeze"/>"#, begin)
+            };
+            format!(r#"<g opacity="0">{}{}</g>"#, anim, shape.svg_element())
+        }).collect();
+        self.wrap_svg(&elements)
+    }
+
+    /// 加上 svg 标签和背景, 组成完整的 SVG 文档
+    fn wrap_svg(&self, elements: &[String]) -> String {
+        let mut svg = String::new();
+        writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+                 w = self.env_width, h = self.env_height).unwrap();
+        let (r, g, b) = self.bg_color;
+        if self.bg_alpha > 0.0 {
+            writeln!(svg, r#"<rect width="100%" height="100%" fill="rgb({},{},{})" fill-opacity="{}"/>"#,
+                     r.round(), g.round(), b.round(), self.bg_alpha).unwrap();
+        }
+        for element in elements {
+            writeln!(svg, "{}", element).unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// 按 z 序逐步叠加图形的动画帧数, 见 for_each_build_up_frame
+    pub fn build_up_frame_count(&self, shapes_per_frame: usize, fade_frames: usize) -> usize {
+        1 + self.shapes.len().div_ceil(shapes_per_frame) * (fade_frames + 1)
+    }
+
+    /// 按 z 序逐步叠加图形, 依次对各帧画面调用 f: 从只有背景开始, 每帧新增 shapes_per_frame 个图形,
+    /// 新增的图形先经过 fade_frames 帧由透明逐渐淡入 (为 0 时直接出现). 在同一张画布上增量绘制, 不保留之前的帧
+    pub fn for_each_build_up_frame<F: FnMut(&Canvas)>(&self, shapes_per_frame: usize, fade_frames: usize, mut f: F) {
+        debug_assert!(shapes_per_frame >= 1, "每帧至少新增一个图形!");
+        let mut canvas = Canvas::new(self.env_height, self.env_width, self.bg_color, self.bg_alpha);
+        let mut faded = canvas.clone();     // 淡入中的画面, 每帧从 canvas 复制后叠加半透明的新图形
+        f(&canvas);
+        for chunk in self.shapes.chunks(shapes_per_frame) {
+            for k in 1..=fade_frames {
+                faded.pixels.copy_from_slice(&canvas.pixels);
+                for shape in chunk {
+                    let mut shape = *shape;
+                    shape.color_mut().a *= k as f32 / (fade_frames + 1) as f32;
+                    shape.draw_to(&mut faded);
+                }
+                f(&faded);
+            }
+            for shape in chunk {
+                shape.draw_to(&mut canvas);
+            }
+            f(&canvas);
+        }
+    }
+
     /// 计算个体的适应度, 存储到 fitness 字段中
     pub fn calc_fitness(&mut self, target: &Canvas) {
         // 避免重复计算
@@ -485,7 +1373,7 @@ impl Individual {
         // 绘制自己的图像
         let selfie = self.draw_self();
         // 与目标图片 target 进行逐像素的比对
-        let diff = Canvas::l2_diff(&selfie, &target);
+        let diff = Canvas::l2_diff(&selfie, target);
         // 存储到 fitness 字段
         self.fitness = Some(diff);
     }
@@ -494,4 +1382,67 @@ impl Individual {
     pub fn get_fitness(&self) -> f32 {
         self.fitness.expect("请先显式调用 calc_fitness 计算适应度!")
     }
+
+    /// 在已经计算的适应度上加上惩罚项 (如视频的时间连贯性惩罚)
+    pub fn add_penalty(&mut self, penalty: f32) {
+        debug_assert!(self.fitness.is_some(), "请先计算适应度!");
+        self.fitness = self.fitness.map(|fitness| fitness + penalty);
+    }
+
+    /// 适应度是否尚未计算 (即下一次 calc_fitness 需要重新绘制)
+    pub fn fitness_unknown(&self) -> bool {
+        self.fitness.is_none()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 背景上一个矩形, 其上一个圆 (颜色为 top_color) 的 20×20 个体
+    fn two_layers(bg_alpha: f32, top_color: (f32, f32, f32)) -> Individual {
+        let (r, g, b) = top_color;
+        Individual::from_json(&format!(r#"{{"version": 2, "height": 20, "width": 20, "bg_color": [10.0, 20.0, 30.0], "bg_alpha": {bg_alpha},
+            "shapes": [{{"type": "rectangle", "p1": {{"x": 2.0, "y": 3.0}}, "p2": {{"x": 12.0, "y": 16.0}}, "color": {{"r": 240.0, "g": 40.0, "b": 90.0, "a": 0.5}}}},
+                       {{"type": "circle", "center": {{"x": 10.0, "y": 10.0}}, "radius": 6.0, "color": {{"r": {r}, "g": {g}, "b": {b}, "a": 0.6}}}}]}}"#))
+    }
+
+    #[test]
+    fn solve_color_recovers_known_color() {
+        for bg_alpha in [1.0, 0.5] {
+            for alpha_mode in [AlphaMode::Ignore, AlphaMode::Match] {
+                let target = Canvas { alpha_mode, ..two_layers(bg_alpha, (200.0, 100.0, 50.0)).draw_self() };
+                let mut ind = two_layers(bg_alpha, (0.0, 0.0, 0.0));
+                ind.solve_color(1, &target, &ColorModel::Free);
+                let Color { r, g, b, .. } = *ind.shapes[1].color();
+                for (solved, expected) in [(r, 200.0), (g, 100.0), (b, 50.0)] {
+                    assert!((solved - expected).abs() < 0.01, "解出的颜色 {:?} 与 (200, 100, 50) 不符", (r, g, b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn solve_color_minimizes_error() {
+        let rgba: Vec<u8> = (0..20 * 20).flat_map(|k| [(k % 20 * 12) as u8, (k / 20 * 12) as u8, 128, if k % 3 == 0 { 100 } else { 255 }]).collect();
+        for bg_alpha in [1.0, 0.5] {
+            for alpha_mode in [AlphaMode::Ignore, AlphaMode::Match] {
+                let target = Canvas { alpha_mode, ..Canvas::from_rgba(20, 20, rgba.clone()).unwrap() };
+                let mut ind = two_layers(bg_alpha, (0.0, 0.0, 0.0));
+                ind.solve_color(1, &target, &ColorModel::Free);
+                ind.calc_fitness(&target);
+                let Color { r, g, b, .. } = *ind.shapes[1].color();
+                // 任一通道偏离最优解后误差都不会变小
+                for (dr, dg, db) in [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)] {
+                    for step in [-3.0, 3.0] {
+                        let mut trial = ind.clone();
+                        trial.shapes[1].color_mut().set_rgb(r + step * dr, g + step * dg, b + step * db, &ColorModel::Free);
+                        trial.calc_fitness(&target);
+                        assert!(trial.get_fitness() >= ind.get_fitness() - 1e-4, "偏离最优解后误差反而变小");
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,146 @@
+use wasm_bindgen::prelude::*;
+use crate::config::Config;
+use crate::genome::GenomeMetadata;
+use crate::island::Archipelago;
+use crate::util::*;
+
+
+/// 在浏览器中运行的演化. 与命令行的 evolve 相同, 只是在单线程上进行, 没有文件读写, 也不按 pyramid 日程切换分辨率
+#[wasm_bindgen]
+pub struct Evolution {
+    cfg: Config,
+    target: Canvas,                 // 按 max_dim 缩放后的目标图片
+    original_size: (usize, usize),  // 缩放前的尺寸, 输出图片按这个尺寸渲染
+    colors: ColorModel,
+    archipelago: Archipelago,
+    gen: usize,
+}
+
+#[wasm_bindgen]
+impl Evolution {
+    /// 由目标图片的 RGBA 像素 (width × height × 4 字节, 逐行排列, 如 ImageData.data) 和 JSON 配置 (同 Config, 省略的字段取默认值) 创建
+    #[wasm_bindgen(constructor)]
+    pub fn new(rgba: &[u8], width: usize, height: usize, config_json: &str) -> Result<Evolution, JsError> {
+        let mut cfg: Config = if config_json.trim().is_empty() {
+            Config::default()
+        } else {
+            serde_json::from_str(config_json).map_err(|e| JsError::new(&format!("配置有误: {}", e)))?
+        };
+        cfg.parallel = false;   // wasm32-unknown-unknown 上不能创建线程
+        cfg.check().map_err(|message| JsError::new(&message))?;
+        cfg.seed_rng();
+        if width == 0 || height == 0 {
+            return Err(JsError::new("图片不能为空"));
+        }
+        let canvas = Canvas::from_rgba(height, width, rgba.to_vec()).ok_or_else(|| JsError::new("像素数据的长度与尺寸不符"))?;
+        let target = cfg.prepare_target(canvas.shrunk(cfg.max_dim, cfg.resize_filter));
+        let colors = cfg.color_model(&target);
+        let archipelago = Archipelago::new(&target, &cfg);
+        Ok(Evolution { cfg, target, original_size: (height, width), colors, archipelago, gen: 0 })
+    }
+
+    /// 演化 n 轮, 返回最优适应度
+    pub fn step(&mut self, n: usize) -> f32 {
+        for _ in 0..n {
+            self.archipelago.step(&self.target, &self.cfg, &self.colors);
+            self.gen += 1;
+        }
+        self.best_fitness()
+    }
+
+    /// 用 JSON 对象修改 Config::TUNABLE_FIELDS 中的参数, 返回修改后的完整配置 (JSON)
+    pub fn set_config(&mut self, patch_json: &str) -> Result<String, JsError> {
+        let patch = serde_json::from_str(patch_json).map_err(|e| JsError::new(&format!("不是合法的 JSON: {}", e)))?;
+        self.cfg = self.cfg.patched(patch).map_err(|message| JsError::new(&message))?;
+        Ok(serde_json::to_string(&self.cfg).unwrap())
+    }
+
+    /// 已经演化的轮数
+    pub fn generation(&self) -> usize {
+        self.gen
+    }
+
+    /// 最优个体的适应度
+    pub fn best_fitness(&self) -> f32 {
+        self.archipelago.best().get_fitness()
+    }
+
+    /// 最优个体的图形数目
+    pub fn best_n_shapes(&self) -> usize {
+        self.archipelago.best().n_shapes()
+    }
+
+    /// 输出图片的宽度 (即目标图片缩放前的宽度)
+    pub fn width(&self) -> usize {
+        self.original_size.1
+    }
+
+    /// 输出图片的高度
+    pub fn height(&self) -> usize {
+        self.original_size.0
+    }
+
+    /// 最优个体按原尺寸渲染的 RGBA 像素 (width × height × 4 字节, 可直接用于 ImageData)
+    pub fn best_rgba(&self) -> Vec<u8> {
+        let (x_height, y_width) = self.original_size;
+        self.archipelago.best().rescaled(x_height, y_width).draw_self().to_image().into_raw()
+    }
+
+    /// 最优个体 (按原尺寸) 的基因 JSON, 与命令行保存的 best.json 格式相同 (元数据中没有时刻和目标图片的散列)
+    pub fn best_genome(&self) -> String {
+        let (x_height, y_width) = self.original_size;
+        let metadata = GenomeMetadata {
+            generation: Some(self.gen),
+            seed: self.cfg.seed,
+            config: Some(serde_json::to_value(&self.cfg).unwrap()),
+            ..Default::default()
+        };
+        self.archipelago.best().rescaled(x_height, y_width).to_json_with_metadata(&metadata)
+    }
+
+    /// 最优个体 (按原尺寸) 的压缩二进制基因 (*.eag), 比 JSON 小得多, 适合传输
+    pub fn best_genome_binary(&self) -> Result<Vec<u8>, JsError> {
+        let (x_height, y_width) = self.original_size;
+        crate::genome::encode_binary(&self.archipelago.best().rescaled(x_height, y_width), None, true).map_err(|message| JsError::new(&message))
+    }
+
+    /// 最优个体 (按原尺寸) 的 SVG
+    pub fn best_svg(&self) -> String {
+        let (x_height, y_width) = self.original_size;
+        self.archipelago.best().rescaled(x_height, y_width).to_svg()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::*;
+    use super::*;
+
+    /// width × height 的渐变图片 (RGBA)
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height).flat_map(|k| [(k % width * 30) as u8, (k / width * 40) as u8, 128, 255]).collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn evolves_from_rgba() {
+        let mut evo = Evolution::new(&gradient(8, 6), 8, 6, r#"{"pop_size": 2, "seed": 7}"#).unwrap();
+        let fitness = evo.step(1);
+        assert!(fitness.is_finite());
+        assert_eq!(evo.generation(), 1);
+        assert_eq!((evo.width(), evo.height()), (8, 6));
+        assert_eq!(evo.best_rgba().len(), 8 * 6 * 4);
+        let genome = Individual::from_json(&evo.best_genome());
+        assert_eq!(genome.env_size(), (6, 8));
+        assert!(evo.set_config(r#"{"mutate_ratio": 0.2}"#).unwrap().contains("\"mutate_ratio\":0.2"));
+        assert!(evo.set_config(r#"{"n_islands": 2}"#).is_err());     // 不能在运行中修改
+    }
+
+    #[wasm_bindgen_test]
+    fn rejects_bad_input() {
+        assert!(Evolution::new(&gradient(8, 6)[..10], 8, 6, "").is_err());     // 长度与尺寸不符
+        assert!(Evolution::new(&[], 0, 0, "").is_err());
+        assert!(Evolution::new(&gradient(8, 6), 8, 6, r#"{"pop_size": 0}"#).is_err());
+        assert!(Evolution::new(&gradient(8, 6), 8, 6, "{").is_err());
+    }
+}